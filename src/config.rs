@@ -1,10 +1,99 @@
+use chrono_tz::Tz;
 use serde::{Deserialize, Serialize};
+use url::Url;
+
+/* Where the known hosts show a commit. {repo} is the repository url, {hash} the full hash */
+const FORGES: [(&str, &str); 6] = [
+    ("github", "{repo}/commit/{hash}"),
+    ("gitlab", "{repo}/-/commit/{hash}"),
+    ("bitbucket", "{repo}/commits/{hash}"),
+    ("gitea", "{repo}/commit/{hash}"),
+    ("forgejo", "{repo}/commit/{hash}"),
+    ("codeberg", "{repo}/commit/{hash}"),
+];
+
+/* For hosts which are not recognised, most of them follow GitHub */
+const DEFAULT_COMMIT_URL: &str = "{repo}/commit/{hash}";
+
+/// When the internal .trk repository is synced with its remote
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncMode {
+    /// Only by trk sync
+    Off,
+    /// Pull before each command, push only by trk sync
+    PullOnly,
+    /// Push after each change, pull only by trk sync
+    PushOnly,
+    /// Pull before and push after each command
+    OnCommand,
+    /// Pull when a session begins and push when it ends
+    OnEndOfSession,
+}
+
+impl SyncMode {
+    const NAMES: [(Self, &'static str); 5] = [
+        (Self::Off, "off"),
+        (Self::PullOnly, "pull-only"),
+        (Self::PushOnly, "push-only"),
+        (Self::OnCommand, "on-command"),
+        (Self::OnEndOfSession, "on-end-of-session"),
+    ];
+
+    /** The name of the mode as trk set-sync takes it */
+    pub fn name(self) -> &'static str {
+        Self::NAMES
+            .iter()
+            .find(|(mode, _)| *mode == self)
+            .map_or("", |(_, name)| name)
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::NAMES
+            .iter()
+            .find(|(_, known)| *known == name)
+            .map(|(mode, _)| *mode)
+    }
+}
+
+/** How this machine syncs the sheet. It is kept in the git config of .trk,
+ * not in the sheet, so each machine has its own.
+ */
+#[derive(Debug, Clone)]
+pub struct SyncConfig {
+    pub mode: SyncMode,
+    /// Remote to sync with instead of the upstream of the branch or the first remote
+    pub remote: Option<String>,
+    /// Branch on the remote instead of the one with the same name
+    pub branch: Option<String>,
+}
+
+impl SyncConfig {
+    pub const fn new() -> Self {
+        Self {
+            mode: SyncMode::OnCommand,
+            remote: None,
+            branch: None,
+        }
+    }
+}
+
+impl Default for SyncConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Config {
     pub show_commits: bool,
     pub repository: Option<String>,
     pub user_name: Option<String>,
+    /// Custom link to a commit with {repo} and {hash} in it, for hosts trk doesn't know
+    #[serde(default)]
+    pub commit_url: Option<String>,
+    /// Timezone all sessions are shown and recorded in, instead of the one of each machine
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<Tz>,
 }
 
 impl Config {
@@ -13,6 +102,195 @@ impl Config {
             show_commits: true,
             repository: None,
             user_name: None,
+            commit_url: None,
+            timezone: None,
+        }
+    }
+
+    /* The repository as web url, also if it was given as ssh remote (git@host:user/repo.git) */
+    fn repository_url(&self) -> Option<String> {
+        let repository = self.repository.as_deref()?.trim();
+        let repository = match repository.split_once("://") {
+            Some(..) => repository.to_string(),
+            None => match repository.split_once(':') {
+                Some((user_host, path)) => {
+                    let host = user_host.rsplit('@').next().unwrap_or(user_host);
+                    format!("https://{host}/{path}")
+                }
+                None => format!("https://{repository}"),
+            },
+        };
+        let repository = repository.trim_end_matches('/');
+        let repository = repository.strip_suffix(".git").unwrap_or(repository);
+        /* ssh://git@host/... remotes become https://host/... */
+        let url = Url::parse(repository).ok()?;
+        let url = match url.scheme() {
+            "http" | "https" => url.to_string(),
+            _ => format!("https://{}{}", url.host_str()?, url.path()),
+        };
+        Some(url.trim_end_matches('/').to_string())
+    }
+
+    /** Link to the page of a commit on the repository host.
+     * None if neither a repository nor a custom link without {repo} is configured.
+     */
+    pub fn commit_url(&self, hash: &str) -> Option<String> {
+        let repository = self.repository_url();
+        let template = match (&self.commit_url, &repository) {
+            (Some(template), _) => template.as_str(),
+            (None, Some(repository)) => {
+                let host = Url::parse(repository).ok()?.host_str()?.to_lowercase();
+                FORGES
+                    .iter()
+                    .find(|(forge, _)| host.contains(forge))
+                    .map_or(DEFAULT_COMMIT_URL, |(_, template)| template)
+            }
+            (None, None) => return None,
+        };
+        if template.contains("{repo}") && repository.is_none() {
+            return None;
+        }
+        Some(
+            template
+                .replace("{repo}", repository.as_deref().unwrap_or_default())
+                .replace("{hash}", hash),
+        )
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASH: &str = "0123456789abcdef0123456789abcdef01234567";
+
+    fn config(repository: Option<&str>, commit_url: Option<&str>) -> Config {
+        let mut config = Config::new();
+        config.repository = repository.map(str::to_string);
+        config.commit_url = commit_url.map(str::to_string);
+        config
+    }
+
+    #[test]
+    fn remotes_become_web_urls() {
+        let remotes = [
+            (
+                "git@github.com:user/repo.git",
+                "https://github.com/user/repo",
+            ),
+            ("git@github.com:user/repo", "https://github.com/user/repo"),
+            (
+                "ssh://git@gitlab.com/group/sub/repo.git",
+                "https://gitlab.com/group/sub/repo",
+            ),
+            (
+                "https://github.com/user/repo.git",
+                "https://github.com/user/repo",
+            ),
+            (
+                "https://github.com/user/repo/",
+                "https://github.com/user/repo",
+            ),
+            ("http://git.example.org/repo", "http://git.example.org/repo"),
+            ("codeberg.org/user/repo", "https://codeberg.org/user/repo"),
+            (
+                "  git@bitbucket.org:team/repo.git  ",
+                "https://bitbucket.org/team/repo",
+            ),
+        ];
+        for (remote, url) in remotes {
+            assert_eq!(
+                config(Some(remote), None).repository_url().as_deref(),
+                Some(url),
+                "{remote}"
+            );
+        }
+        assert_eq!(config(None, None).repository_url(), None);
+    }
+
+    #[test]
+    fn known_hosts_link_their_way() {
+        let links = [
+            (
+                "git@github.com:user/repo.git",
+                "https://github.com/user/repo/commit/",
+            ),
+            (
+                "https://gitlab.com/group/repo",
+                "https://gitlab.com/group/repo/-/commit/",
+            ),
+            (
+                "https://gitlab.example.com/repo",
+                "https://gitlab.example.com/repo/-/commit/",
+            ),
+            (
+                "git@bitbucket.org:team/repo.git",
+                "https://bitbucket.org/team/repo/commits/",
+            ),
+            (
+                "https://gitea.example.com/user/repo",
+                "https://gitea.example.com/user/repo/commit/",
+            ),
+            (
+                "https://codeberg.org/user/repo",
+                "https://codeberg.org/user/repo/commit/",
+            ),
+            (
+                "https://GitHub.com/user/repo",
+                "https://github.com/user/repo/commit/",
+            ),
+            /* Unknown hosts are taken to work like GitHub */
+            (
+                "git@git.example.org:user/repo.git",
+                "https://git.example.org/user/repo/commit/",
+            ),
+        ];
+        for (remote, link) in links {
+            assert_eq!(
+                config(Some(remote), None).commit_url(HASH),
+                Some(format!("{link}{HASH}")),
+                "{remote}"
+            );
+        }
+    }
+
+    #[test]
+    fn templates_for_self_hosted_forges() {
+        let repository = Some("git@git.example.org:group/repo.git");
+        let templates = [
+            (
+                repository,
+                "{repo}/-/commit/{hash}",
+                Some(format!(
+                    "https://git.example.org/group/repo/-/commit/{HASH}"
+                )),
+            ),
+            (
+                repository,
+                "https://cgit.example.org/repo/commit/?id={hash}",
+                Some(format!("https://cgit.example.org/repo/commit/?id={HASH}")),
+            ),
+            (
+                None,
+                "https://cgit.example.org/repo/commit/?id={hash}",
+                Some(format!("https://cgit.example.org/repo/commit/?id={HASH}")),
+            ),
+            /* Nothing to put in for {repo} */
+            (None, "{repo}/-/commit/{hash}", None),
+        ];
+        for (repository, template, link) in templates {
+            assert_eq!(
+                config(repository, Some(template)).commit_url(HASH),
+                link,
+                "{template}"
+            );
         }
+        assert_eq!(config(None, None).commit_url(HASH), None);
     }
 }
@@ -0,0 +1,76 @@
+/* Checks of the timesheet file and recovery from the history in .trk.
+ *
+ * A sheet which can't be read or breaks the invariants of the sessions is diagnosed
+ * together with the newest revision of the history which is fine, so trk doctor --repair
+ * can go back to it like trk restore.
+ */
+
+use std::fmt::Write;
+
+use crate::error::TrkError;
+use crate::git::Revision;
+use crate::sheet::timesheet::Timesheet;
+use crate::storage::Storage;
+use crate::util::{system_timezone, ts_to_date};
+
+pub struct Diagnosis {
+    pub problems: Vec<String>,
+    /// Newest revision without problems, None if the sheet is fine or no revision is
+    pub last_good: Option<Revision>,
+}
+
+impl Diagnosis {
+    pub fn report(&self) -> String {
+        if self.problems.is_empty() {
+            return String::from("No problems found.\n");
+        }
+        let mut report = String::new();
+        for problem in &self.problems {
+            writeln!(report, "{problem}").unwrap();
+        }
+        match &self.last_good {
+            Some(revision) => writeln!(
+                report,
+                "Last good revision: {}  {}  {}",
+                &revision.hash[..revision.hash.len().min(8)],
+                ts_to_date(revision.time, system_timezone()),
+                revision.subject
+            )
+            .unwrap(),
+            None => writeln!(report, "No revision in .trk is without problems.").unwrap(),
+        }
+        report
+    }
+}
+
+/* Problems of a sheet in its JSON representation */
+fn problems(serialized: &str) -> Vec<String> {
+    match Timesheet::parse(serialized) {
+        Ok(sheet) => sheet.check(),
+        Err(e) => vec![e.to_string()],
+    }
+}
+
+/** Check .trk/timesheet.json and find the revision to recover from if need be */
+pub fn diagnose(storage: &Storage) -> Result<Diagnosis, TrkError> {
+    let problems = match storage.load() {
+        Ok(Some(sheet)) => sheet.check(),
+        Ok(None) => return Err(TrkError::NotInitialised),
+        Err(e) => vec![e.to_string()],
+    };
+    if problems.is_empty() {
+        return Ok(Diagnosis {
+            problems,
+            last_good: None,
+        });
+    }
+    let last_good = storage.history()?.into_iter().find(|revision| {
+        storage
+            .revision_json(&revision.hash)
+            .is_ok_and(|serialized| self::problems(&serialized).is_empty())
+    });
+    Ok(Diagnosis {
+        problems,
+        last_good,
+    })
+}
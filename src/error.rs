@@ -0,0 +1,95 @@
+use std::fmt;
+use std::io;
+
+/** Everything that can go wrong in trk.
+ * Each variant maps to its own process exit code, see exit_code().
+ */
+#[derive(Debug)]
+pub enum TrkError {
+    /// A command line argument that makes no sense, like `trk status foo`
+    InvalidArgument(String),
+    /// A time argument which could not be parsed
+    InvalidTime(String),
+    /// A time which would break the ordering of the sheet
+    InvalidTimestamp,
+    /// A change which would break the ordering or the pause/resume pairing of a session
+    Inconsistent(String),
+    /// No .trk directory here or in any parent directory
+    NoTrkDir,
+    NotInitialised,
+    AlreadyInitialised,
+    /// No name given to init and none configured in git
+    NoUserName,
+    NoSession,
+    /// The last session has to be ended first
+    SessionRunning,
+    /// The last session is already finalized
+    NotRunning,
+    AlreadyPaused,
+    NotPaused,
+    Io(io::Error),
+    Git(String),
+    /// The timesheet file exists but can't be read as a timesheet
+    Corrupt(String),
+    /// A report template, maybe one from .trk/templates, could not be rendered
+    Template(String),
+}
+
+impl TrkError {
+    pub const fn exit_code(&self) -> i32 {
+        match self {
+            Self::InvalidArgument(..) => 2,
+            Self::InvalidTime(..) => 3,
+            Self::InvalidTimestamp => 4,
+            Self::Inconsistent(..) => 5,
+            Self::NoTrkDir => 10,
+            Self::NotInitialised => 11,
+            Self::AlreadyInitialised => 12,
+            Self::NoUserName => 13,
+            Self::NoSession => 20,
+            Self::SessionRunning => 21,
+            Self::NotRunning => 22,
+            Self::AlreadyPaused => 23,
+            Self::NotPaused => 24,
+            Self::Io(..) => 30,
+            Self::Git(..) => 31,
+            Self::Corrupt(..) => 32,
+            Self::Template(..) => 33,
+        }
+    }
+}
+
+impl fmt::Display for TrkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidArgument(message) => write!(f, "{message}"),
+            Self::InvalidTime(message) => write!(f, "Invalid time: {message}."),
+            Self::InvalidTimestamp => write!(f, "That timestamp is before the last event."),
+            Self::Inconsistent(problem) => write!(f, "Not possible, {problem}."),
+            Self::NoTrkDir => write!(f, "Fatal: not a .trk directory (or subdirectory of one)."),
+            Self::NotInitialised => write!(f, "No timesheet file! You might have to init first."),
+            Self::AlreadyInitialised => write!(f, "Already initialised."),
+            Self::NoUserName => write!(
+                f,
+                "Empty name not permitted.\n\tPlease run with 'trk init <name>'"
+            ),
+            Self::NoSession => write!(f, "No session yet."),
+            Self::SessionRunning => write!(f, "Last session is still running."),
+            Self::NotRunning => write!(f, "Already finalized, no session running."),
+            Self::AlreadyPaused => write!(f, "Already paused."),
+            Self::NotPaused => write!(f, "Currently not paused."),
+            Self::Io(e) => write!(f, "IO error: {e}"),
+            Self::Git(message) => write!(f, "git failed: {message}"),
+            Self::Corrupt(message) => write!(f, "The timesheet file is corrupt: {message}"),
+            Self::Template(message) => write!(f, "Could not render the report: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for TrkError {}
+
+impl From<io::Error> for TrkError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
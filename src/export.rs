@@ -0,0 +1,480 @@
+/* Flat exports of the timesheet for spreadsheets and scripts.
+ *
+ * The schema is documented in the README and versioned by EXPORT_SCHEMA.
+ * Fields are only ever added at the end, never renamed or removed, without bumping it.
+ */
+
+use std::io::Write;
+
+use chrono::{SecondsFormat, TimeZone};
+use chrono_tz::Tz;
+use serde::Serialize;
+
+use crate::error::TrkError;
+use crate::sheet::filter::Filter;
+use crate::sheet::session::{CommitDetails, Event, EventType, Session};
+use crate::sheet::timesheet::Timesheet;
+
+pub const EXPORT_SCHEMA: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+    Jsonl,
+}
+
+/// Which rows a csv export contains. json and jsonl always contain both.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Records {
+    Sessions,
+    Events,
+}
+
+#[derive(Serialize)]
+struct SessionRow<'a> {
+    session: usize,
+    start: u64,
+    start_time: String,
+    /// None while the session is running
+    end: Option<u64>,
+    end_time: Option<String>,
+    work_seconds: u64,
+    pause_seconds: u64,
+    project: Option<&'a str>,
+    tags: Vec<&'a str>,
+    branches: Vec<&'a str>,
+    /// The timezone of start_time and end_time and of the times of the session's events
+    timezone: &'static str,
+}
+
+#[derive(Serialize)]
+struct EventRow<'a> {
+    session: usize,
+    event: usize,
+    timestamp: u64,
+    time: String,
+    #[serde(rename = "type")]
+    ty: &'static str,
+    note: Option<String>,
+    hash: Option<&'a str>,
+    tags: Vec<&'a str>,
+    author: Option<&'a str>,
+    insertions: Option<u64>,
+    deletions: Option<u64>,
+}
+
+/* One line of a jsonl export */
+#[derive(Serialize)]
+#[serde(tag = "record", rename_all = "lowercase")]
+enum Record<'a> {
+    Session(SessionRow<'a>),
+    Event(EventRow<'a>),
+}
+
+#[derive(Serialize)]
+struct Document<'a> {
+    schema: u32,
+    sessions: Vec<SessionRow<'a>>,
+    events: Vec<EventRow<'a>>,
+}
+
+/* RFC 3339 in the timezone of the session, which spreadsheets understand */
+fn rfc3339(timestamp: u64, tz: Tz) -> String {
+    tz.timestamp_millis_opt(timestamp as i64)
+        .unwrap()
+        .to_rfc3339_opts(SecondsFormat::Secs, false)
+}
+
+/* The sheet counts in milliseconds, the export in seconds like unix timestamps */
+const fn seconds(milliseconds: u64) -> u64 {
+    milliseconds / 1000
+}
+
+fn session_row(index: usize, session: &Session, now: u64, tz: Tz) -> SessionRow<'_> {
+    let end = (!session.is_running()).then_some(session.end);
+    SessionRow {
+        session: index,
+        start: seconds(session.start),
+        start_time: rfc3339(session.start, tz),
+        end: end.map(seconds),
+        end_time: end.map(|end| rfc3339(end, tz)),
+        work_seconds: seconds(session.work_time(now)),
+        pause_seconds: seconds(session.pause_time(now)),
+        project: session.project(),
+        tags: session.tags().iter().map(String::as_str).collect(),
+        branches: session.branches().map(String::as_str).collect(),
+        timezone: tz.name(),
+    }
+}
+
+fn details(event: &Event) -> Option<&CommitDetails> {
+    match event.ev_ty() {
+        EventType::Commit { details, .. } => details.as_ref(),
+        _ => None,
+    }
+}
+
+fn event_rows(index: usize, session: &Session, tz: Tz) -> impl Iterator<Item = EventRow<'_>> {
+    session
+        .events()
+        .iter()
+        .enumerate()
+        .map(move |(event_index, event)| EventRow {
+            session: index,
+            event: event_index,
+            timestamp: seconds(event.timestamp()),
+            time: rfc3339(event.timestamp(), tz),
+            ty: event.ev_ty().name(),
+            /* The whole message for commits, like git log shows it */
+            note: match event.commit_message() {
+                Some((subject, "")) => Some(subject.to_string()),
+                Some((subject, body)) => Some(format!("{subject}\n\n{body}")),
+                None => event.note().map(|note| note.trim_end().to_string()),
+            }
+            .filter(|note| !note.is_empty()),
+            hash: match event.ev_ty() {
+                EventType::Commit { hash, .. } => Some(hash.as_str()),
+                _ => None,
+            },
+            tags: event.tags().iter().map(String::as_str).collect(),
+            author: details(event).map(|details| details.author.as_str()),
+            insertions: details(event).map(|details| details.insertions),
+            deletions: details(event).map(|details| details.deletions),
+        })
+}
+
+/* Lists are joined with ';' in csv */
+fn csv_list(list: &[&str]) -> String {
+    list.join(";")
+}
+
+fn csv_option<T: ToString>(value: Option<T>) -> String {
+    value.map(|value| value.to_string()).unwrap_or_default()
+}
+
+fn write_csv<'a>(
+    out: impl Write,
+    records: Records,
+    sessions: impl Iterator<Item = (usize, &'a Session, Tz)>,
+    now: u64,
+) -> Result<(), csv::Error> {
+    let mut writer = csv::Writer::from_writer(out);
+    match records {
+        Records::Sessions => {
+            writer.write_record([
+                "session",
+                "start",
+                "start_time",
+                "end",
+                "end_time",
+                "work_seconds",
+                "pause_seconds",
+                "project",
+                "tags",
+                "branches",
+                "timezone",
+            ])?;
+            for (index, session, tz) in sessions {
+                let row = session_row(index, session, now, tz);
+                writer.write_record([
+                    row.session.to_string(),
+                    row.start.to_string(),
+                    row.start_time,
+                    csv_option(row.end),
+                    csv_option(row.end_time),
+                    row.work_seconds.to_string(),
+                    row.pause_seconds.to_string(),
+                    csv_option(row.project),
+                    csv_list(&row.tags),
+                    csv_list(&row.branches),
+                    row.timezone.to_string(),
+                ])?;
+            }
+        }
+        Records::Events => {
+            writer.write_record([
+                "session",
+                "event",
+                "timestamp",
+                "time",
+                "type",
+                "note",
+                "hash",
+                "tags",
+                "author",
+                "insertions",
+                "deletions",
+            ])?;
+            for (index, session, tz) in sessions {
+                for row in event_rows(index, session, tz) {
+                    writer.write_record([
+                        row.session.to_string(),
+                        row.event.to_string(),
+                        row.timestamp.to_string(),
+                        row.time,
+                        row.ty.to_string(),
+                        csv_option(row.note),
+                        csv_option(row.hash),
+                        csv_list(&row.tags),
+                        csv_option(row.author),
+                        csv_option(row.insertions),
+                        csv_option(row.deletions),
+                    ])?;
+                }
+            }
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/** Write the sessions selected by the filter and their events.
+ * Session indices are the ones shown by `trk list`, also when filtering.
+ * Times are in the timezone each session is shown in, see Session::timezone.
+ */
+pub fn export(
+    sheet: &Timesheet,
+    filter: &Filter,
+    format: ExportFormat,
+    records: Records,
+    mut out: impl Write,
+) -> Result<(), TrkError> {
+    let now = sheet.now();
+    let sessions = sheet
+        .sessions()
+        .iter()
+        .enumerate()
+        .filter(|(_, session)| filter.matches(session))
+        .map(|(index, session)| (index, session, session.timezone(sheet.shown_in())));
+
+    match format {
+        ExportFormat::Csv => {
+            write_csv(out, records, sessions, now).map_err(|e| match e.into_kind() {
+                csv::ErrorKind::Io(e) => TrkError::Io(e),
+                kind => TrkError::InvalidArgument(format!("Could not write csv: {kind:?}")),
+            })
+        }
+        ExportFormat::Json => {
+            let (sessions, events): (Vec<_>, Vec<_>) = sessions
+                .map(|(index, session, tz)| {
+                    (
+                        session_row(index, session, now, tz),
+                        event_rows(index, session, tz).collect::<Vec<EventRow>>(),
+                    )
+                })
+                .unzip();
+            let document = Document {
+                schema: EXPORT_SCHEMA,
+                sessions,
+                events: events.into_iter().flatten().collect(),
+            };
+            serde_json::to_writer_pretty(&mut out, &document).map_err(std::io::Error::from)?;
+            writeln!(out)?;
+            Ok(())
+        }
+        ExportFormat::Jsonl => {
+            for (index, session, tz) in sessions {
+                let rows = std::iter::once(Record::Session(session_row(index, session, now, tz)))
+                    .chain(event_rows(index, session, tz).map(Record::Event));
+                for row in rows {
+                    serde_json::to_writer(&mut out, &row).map_err(std::io::Error::from)?;
+                    writeln!(out)?;
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    use serde_json::Value;
+
+    use crate::clock::FakeClock;
+
+    const START: u64 = 1_700_000_000_000;
+    const MINUTE: u64 = 60 * 1000;
+    const NOTE: &str = "ask \"Bob\", then\nwrite it down";
+
+    /* An ended session of acme with a note, a pause and a commit, and a running one */
+    fn sheet() -> Timesheet {
+        let clock = Arc::new(FakeClock::new(START));
+        let mut sheet = Timesheet::with_clock(String::from("tester"), clock.clone());
+        sheet.show_in(Tz::UTC);
+        sheet.new_session(None).unwrap();
+        sheet
+            .tag_session(
+                None,
+                Some(String::from("acme")),
+                vec![String::from("review")],
+            )
+            .unwrap();
+        clock.advance(10 * MINUTE);
+        sheet
+            .note(None, String::from(NOTE), vec![String::from("meeting")])
+            .unwrap();
+        clock.advance(10 * MINUTE);
+        sheet.pause(None, None).unwrap();
+        clock.advance(5 * MINUTE);
+        sheet.resume(None).unwrap();
+        clock.advance(5 * MINUTE);
+        let details = CommitDetails {
+            author: String::from("Tester <t@t>"),
+            date: START + 30 * MINUTE,
+            subject: String::from("Fix export"),
+            body: String::from("Quote notes."),
+            files: vec![String::from("src/export.rs")],
+            insertions: 12,
+            deletions: 3,
+        };
+        sheet
+            .add_commit(String::from("abc1234"), Some(details))
+            .unwrap();
+        clock.advance(30 * MINUTE);
+        sheet.end_session(None).unwrap();
+        clock.advance(60 * MINUTE);
+        sheet.new_session(None).unwrap();
+        clock.advance(15 * MINUTE);
+        sheet
+    }
+
+    fn exported(
+        sheet: &Timesheet,
+        filter: &Filter,
+        format: ExportFormat,
+        records: Records,
+    ) -> String {
+        let mut out = Vec::new();
+        export(sheet, filter, format, records, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn csv_sessions() {
+        let csv = exported(
+            &sheet(),
+            &Filter::default(),
+            ExportFormat::Csv,
+            Records::Sessions,
+        );
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "session,start,start_time,end,end_time,work_seconds,pause_seconds,project,tags,branches,timezone",
+                "0,1700000000,2023-11-14T22:13:20+00:00,1700003600,2023-11-14T23:13:20+00:00,3300,300,acme,review,,UTC",
+                "1,1700007200,2023-11-15T00:13:20+00:00,,,900,0,,,,UTC",
+            ]
+        );
+    }
+
+    #[test]
+    fn csv_quotes_notes() {
+        let csv = exported(
+            &sheet(),
+            &Filter::default(),
+            ExportFormat::Csv,
+            Records::Events,
+        );
+        assert!(csv.contains("\"ask \"\"Bob\"\", then\nwrite it down\""));
+        let mut reader = csv::Reader::from_reader(csv.as_bytes());
+        let rows: Vec<csv::StringRecord> = reader.records().map(Result::unwrap).collect();
+        assert_eq!(rows.len(), 4);
+        assert_eq!(&rows[0][4], "Note");
+        assert_eq!(&rows[0][5], NOTE);
+        assert_eq!(&rows[0][7], "meeting");
+        assert_eq!(&rows[1][5], "");
+        assert_eq!(&rows[3][4], "Commit");
+        assert_eq!(&rows[3][5], "Fix export\n\nQuote notes.");
+        assert_eq!(
+            rows[3].iter().skip(6).collect::<Vec<&str>>(),
+            ["abc1234", "", "Tester <t@t>", "12", "3"]
+        );
+    }
+
+    #[test]
+    fn json_schema() {
+        let json = exported(
+            &sheet(),
+            &Filter::default(),
+            ExportFormat::Json,
+            Records::Sessions,
+        );
+        let document: Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(document["schema"], EXPORT_SCHEMA);
+        let sessions = document["sessions"].as_array().unwrap();
+        assert_eq!(sessions.len(), 2);
+        assert_eq!(sessions[0]["tags"], serde_json::json!(["review"]));
+        assert_eq!(sessions[0]["project"], "acme");
+        assert_eq!(sessions[0]["timezone"], "UTC");
+        assert_eq!(sessions[1]["end"], Value::Null);
+        assert_eq!(sessions[1]["project"], Value::Null);
+        let events = document["events"].as_array().unwrap();
+        assert_eq!(events.len(), 4);
+        assert_eq!(events[0]["note"], NOTE);
+        assert_eq!(events[0]["time"], "2023-11-14T22:23:20+00:00");
+        assert_eq!(events[3]["type"], "Commit");
+        assert_eq!(events[3]["insertions"], 12);
+        assert_eq!(events[1]["hash"], Value::Null);
+    }
+
+    #[test]
+    fn jsonl_has_each_session_before_its_events() {
+        let jsonl = exported(
+            &sheet(),
+            &Filter::default(),
+            ExportFormat::Jsonl,
+            Records::Sessions,
+        );
+        let records: Vec<Value> = jsonl
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let kinds: Vec<&str> = records
+            .iter()
+            .map(|record| record["record"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            kinds,
+            ["session", "event", "event", "event", "event", "session"]
+        );
+        assert_eq!(records[5]["session"], 1);
+        assert_eq!(records[5]["work_seconds"], 900);
+    }
+
+    #[test]
+    fn filtered_export_keeps_the_indices() {
+        let sheet = sheet();
+        let since = Filter {
+            since: Some(START + 30 * MINUTE),
+            ..Filter::default()
+        };
+        let csv = exported(&sheet, &since, ExportFormat::Csv, Records::Sessions);
+        assert_eq!(csv.lines().count(), 3);
+
+        let project = Filter {
+            project: Some(String::from("acme")),
+            ..Filter::default()
+        };
+        let csv = exported(&sheet, &project, ExportFormat::Csv, Records::Events);
+        let mut reader = csv::Reader::from_reader(csv.as_bytes());
+        let sessions: Vec<String> = reader
+            .records()
+            .map(|row| row.unwrap()[0].to_string())
+            .collect();
+        assert_eq!(sessions, ["0", "0", "0", "0"]);
+        let running = Filter {
+            since: Some(START + 90 * MINUTE),
+            ..Filter::default()
+        };
+        let json = exported(&sheet, &running, ExportFormat::Json, Records::Sessions);
+        let document: Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(document["sessions"][0]["session"], 1);
+        assert_eq!(document["sessions"].as_array().unwrap().len(), 1);
+        assert_eq!(document["events"], serde_json::json!([]));
+    }
+}
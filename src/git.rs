@@ -0,0 +1,534 @@
+/* Everything trk does with git, in process through libgit2.
+ *
+ * There are two repositories: the internal one in .trk, which holds the history of
+ * timesheet.json, and the project's repository, whose commits go into the sheet.
+ * Functions for the internal one take the .trk directory, the others a directory
+ * from which the project's repository is looked for upwards.
+ */
+
+use std::cell::Cell;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use git2::{
+    Commit, Cred, CredentialType, DiffOptions, FetchOptions, Oid, PushOptions, RemoteCallbacks,
+    Repository, Signature, Sort,
+};
+
+use crate::config::{SyncConfig, SyncMode};
+use crate::error::TrkError;
+use crate::sheet::session::CommitDetails;
+
+const SHEET_FILE: &str = "timesheet.json";
+const MERGE_DRIVER: &str = "trk merge-driver %O %A %B";
+
+/* Keys of the sync settings in the config of the internal repository */
+const SYNC_MODE: &str = "trk.sync.mode";
+const SYNC_REMOTE: &str = "trk.sync.remote";
+const SYNC_BRANCH: &str = "trk.sync.branch";
+
+/* Ask for credentials this often before giving up, libgit2 asks again after each failure */
+const CREDENTIAL_ATTEMPTS: usize = 3;
+
+/* Milliseconds to wait for a remote to answer */
+const CONNECT_TIMEOUT: i32 = 5_000;
+const SERVER_TIMEOUT: i32 = 20_000;
+
+impl From<git2::Error> for TrkError {
+    fn from(e: git2::Error) -> Self {
+        Self::Git(e.message().to_string())
+    }
+}
+
+fn trk_repo(trk_dir: &Path) -> Result<Repository, TrkError> {
+    if !trk_dir.exists() {
+        return Err(TrkError::NoTrkDir);
+    }
+    Ok(Repository::open(trk_dir)?)
+}
+
+fn project_repo(dir: &Path) -> Result<Repository, TrkError> {
+    Ok(Repository::discover(dir)?)
+}
+
+/* The committer configured in git, or trk itself if there is none */
+fn signature(repo: &Repository) -> Result<Signature<'static>, TrkError> {
+    Ok(repo
+        .signature()
+        .or_else(|_| Signature::now("trk", "trk@localhost"))?)
+}
+
+/* Plain git in .trk merges diverged sheets with trk merge-driver instead of line by line */
+fn register_merge_driver(repo: &Repository) -> Result<(), TrkError> {
+    let mut config = repo.config()?.open_level(git2::ConfigLevel::Local)?;
+    if config.get_string("merge.trk.driver").ok().as_deref() != Some(MERGE_DRIVER) {
+        config.set_str("merge.trk.name", "trk timesheet merge")?;
+        config.set_str("merge.trk.driver", MERGE_DRIVER)?;
+    }
+    let attributes = repo.path().join("info").join("attributes");
+    let line = format!("{SHEET_FILE} merge=trk");
+    let content = fs::read_to_string(&attributes).unwrap_or_default();
+    if !content.lines().any(|known| known == line) {
+        fs::create_dir_all(repo.path().join("info"))?;
+        fs::write(&attributes, format!("{content}{line}\n"))?;
+    }
+    Ok(())
+}
+
+pub fn git_init_trk(trk_dir: &Path) -> Result<(), TrkError> {
+    let repo = Repository::init(trk_dir)?;
+    register_merge_driver(&repo)
+}
+
+/* A commit of content as timesheet.json, on top of the tree of the first parent */
+fn commit_sheet(
+    repo: &Repository,
+    content: &str,
+    message: &str,
+    parents: &[&Commit],
+) -> Result<Oid, TrkError> {
+    let blob = repo.blob(content.as_bytes())?;
+    let base = parents.first().map(|parent| parent.tree()).transpose()?;
+    let mut builder = repo.treebuilder(base.as_ref())?;
+    builder.insert(SHEET_FILE, blob, 0o100644)?;
+    let tree = repo.find_tree(builder.write()?)?;
+    let signature = signature(repo)?;
+    Ok(repo.commit(None, &signature, &signature, message, &tree, parents)?)
+}
+
+/* Move the checked out branch, HEAD points to it even before its first commit */
+fn move_branch(repo: &Repository, commit: Oid, message: &str) -> Result<(), TrkError> {
+    let head = repo.find_reference("HEAD")?;
+    match head.symbolic_target() {
+        Some(branch) => {
+            repo.reference(branch, commit, true, message)?;
+        }
+        None => repo.set_head_detached(commit)?,
+    }
+    Ok(())
+}
+
+fn head_commit(repo: &Repository) -> Result<Option<Commit<'_>>, TrkError> {
+    match repo.head() {
+        Ok(head) => Ok(Some(head.peel_to_commit()?)),
+        Err(e) if e.code() == git2::ErrorCode::UnbornBranch => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn sheet_of(repo: &Repository, commit: &Commit) -> Result<String, TrkError> {
+    let entry = commit.tree()?.get_path(Path::new(SHEET_FILE))?;
+    let blob = entry.to_object(repo)?.peel_to_blob()?;
+    String::from_utf8(blob.content().to_vec())
+        .map_err(|e| TrkError::Corrupt(format!("{SHEET_FILE} at {}: {e}", commit.id())))
+}
+
+/** Commit content as timesheet.json of the internal repository.
+ * write puts the same content into the working tree. It runs after the commit is created
+ * but before the branch is moved to it, so either both happen or the branch stays where it was.
+ */
+pub fn git_commit_trk(
+    trk_dir: &Path,
+    content: &str,
+    message: &str,
+    write: impl FnOnce() -> Result<(), TrkError>,
+) -> Result<(), TrkError> {
+    let repo = trk_repo(trk_dir)?;
+    let parent = head_commit(&repo)?;
+
+    /* Commands like add_branch on a finalized session change nothing */
+    if let Some(ref parent) = parent {
+        if sheet_of(&repo, parent).is_ok_and(|known| known == content) {
+            return write();
+        }
+    }
+    let parents: Vec<_> = parent.iter().collect();
+    let commit = commit_sheet(&repo, content, message, &parents)?;
+    write()?;
+    move_branch(&repo, commit, &format!("commit: {message}"))?;
+    let mut index = repo.index()?;
+    index.add_path(Path::new(SHEET_FILE))?;
+    index.write()?;
+    Ok(())
+}
+
+/* A commit of the internal .trk repository */
+pub struct Revision {
+    pub hash: String,
+    /// Commit time in milliseconds, like the timestamps of the sheet
+    pub time: u64,
+    pub subject: String,
+}
+
+/* History of the timesheet, newest first */
+pub fn git_log_trk(trk_dir: &Path) -> Result<Vec<Revision>, TrkError> {
+    let repo = trk_repo(trk_dir)?;
+    let mut walk = repo.revwalk()?;
+    walk.set_sorting(Sort::TOPOLOGICAL | Sort::TIME)?;
+    walk.push_head()?;
+    walk.map(|oid| {
+        let commit = repo.find_commit(oid?)?;
+        Ok(Revision {
+            hash: commit.id().to_string(),
+            time: commit.time().seconds() as u64 * 1000,
+            subject: commit.summary().unwrap_or_default().to_string(),
+        })
+    })
+    .collect()
+}
+
+/* Full hash of a revision of the internal repository, like HEAD~2 or an abbreviated hash */
+pub fn git_resolve_trk(trk_dir: &Path, revision: &str) -> Result<String, TrkError> {
+    let repo = trk_repo(trk_dir)?;
+    let commit = repo.revparse_single(revision)?.peel_to_commit()?;
+    Ok(commit.id().to_string())
+}
+
+/* Content of timesheet.json at the given revision */
+pub fn git_show_trk(trk_dir: &Path, revision: &str) -> Result<String, TrkError> {
+    let repo = trk_repo(trk_dir)?;
+    let blob = repo
+        .revparse_single(&format!("{revision}:{SHEET_FILE}"))?
+        .peel_to_blob()?;
+    String::from_utf8(blob.content().to_vec())
+        .map_err(|e| TrkError::Corrupt(format!("{SHEET_FILE} at {revision}: {e}")))
+}
+
+/* The local branch of the internal repository and where on the remote it is synced to */
+struct SyncTarget {
+    /// Full name of the checked out branch, refs/heads/...
+    local: String,
+    remote: String,
+    /// Short name of the branch on the remote
+    branch: String,
+}
+
+impl SyncTarget {
+    /* Where the remote's branch is remembered between syncs */
+    fn tracking(&self) -> String {
+        format!("refs/remotes/{}/{}", self.remote, self.branch)
+    }
+}
+
+/* None if there is no remote to sync with */
+fn sync_target(repo: &Repository, sync: &SyncConfig) -> Result<Option<SyncTarget>, TrkError> {
+    let remotes = repo.remotes()?;
+    if remotes.is_empty() {
+        return Ok(None);
+    }
+    let head = repo.find_reference("HEAD")?;
+    let Some(local) = head.symbolic_target().map(str::to_string) else {
+        return Err(TrkError::Git(String::from(
+            "HEAD of the internal repository is detached",
+        )));
+    };
+    let remote = match (&sync.remote, repo.branch_upstream_remote(&local)) {
+        (Some(remote), _) => remote.clone(),
+        (None, Ok(remote)) => remote.as_str().unwrap_or_default().to_string(),
+        (None, Err(_)) => remotes.get(0).unwrap_or_default().to_string(),
+    };
+    let branch = sync
+        .branch
+        .clone()
+        .unwrap_or_else(|| local.trim_start_matches("refs/heads/").to_string());
+    Ok(Some(SyncTarget {
+        local,
+        remote,
+        branch,
+    }))
+}
+
+/* The sync settings of this machine, from .trk/.git/config which isn't synced */
+pub fn git_sync_config(trk_dir: &Path) -> Result<SyncConfig, TrkError> {
+    let repo = trk_repo(trk_dir)?;
+    let config = repo.config()?.open_level(git2::ConfigLevel::Local)?;
+    let mode = match config.get_string(SYNC_MODE) {
+        Ok(name) => SyncMode::from_name(&name).ok_or_else(|| {
+            TrkError::InvalidArgument(format!("Unknown {SYNC_MODE} '{name}' in .trk/.git/config"))
+        })?,
+        Err(_) => SyncConfig::new().mode,
+    };
+    Ok(SyncConfig {
+        mode,
+        remote: config.get_string(SYNC_REMOTE).ok(),
+        branch: config.get_string(SYNC_BRANCH).ok(),
+    })
+}
+
+pub fn git_set_sync_config(trk_dir: &Path, sync: &SyncConfig) -> Result<(), TrkError> {
+    let repo = trk_repo(trk_dir)?;
+    let mut config = repo.config()?.open_level(git2::ConfigLevel::Local)?;
+    config.set_str(SYNC_MODE, sync.mode.name())?;
+    for (key, value) in [(SYNC_REMOTE, &sync.remote), (SYNC_BRANCH, &sync.branch)] {
+        match value {
+            Some(value) => config.set_str(key, value)?,
+            None => match config.remove(key) {
+                Err(e) if e.code() != git2::ErrorCode::NotFound => return Err(e.into()),
+                _ => {}
+            },
+        }
+    }
+    Ok(())
+}
+
+/** Give up on unreachable remotes after a few seconds instead of hanging while offline.
+ * The timeouts are global to libgit2 and apply to every repository of the process.
+ *
+ * # Safety
+ * libgit2 must not be in use by any other thread while this runs,
+ * so call it at the start of the program, before any thread is spawned.
+ */
+pub unsafe fn limit_timeouts() {
+    let _ = git2::opts::set_server_connect_timeout_in_milliseconds(CONNECT_TIMEOUT);
+    let _ = git2::opts::set_server_timeout_in_milliseconds(SERVER_TIMEOUT);
+}
+
+/* Credentials from the ssh agent, the default ssh keys or the configured credential helper */
+fn callbacks<'a>(config: &'a git2::Config) -> RemoteCallbacks<'a> {
+    let mut callbacks = RemoteCallbacks::new();
+    let attempts = Cell::new(0);
+    callbacks.credentials(move |url, username, allowed| {
+        attempts.set(attempts.get() + 1);
+        if attempts.get() > CREDENTIAL_ATTEMPTS {
+            return Err(git2::Error::from_str(
+                "no credentials accepted by the remote",
+            ));
+        }
+        if allowed.contains(CredentialType::SSH_KEY) {
+            let username = username.unwrap_or("git");
+            if attempts.get() == 1 {
+                return Cred::ssh_key_from_agent(username);
+            }
+            let home = std::env::var_os("HOME")
+                .map(PathBuf::from)
+                .unwrap_or_default();
+            let key = ["id_ed25519", "id_ecdsa", "id_rsa"]
+                .iter()
+                .map(|name| home.join(".ssh").join(name))
+                .find(|path| path.exists());
+            if let Some(key) = key {
+                return Cred::ssh_key(username, None, &key, None);
+            }
+        }
+        if allowed.contains(CredentialType::USER_PASS_PLAINTEXT) {
+            return Cred::credential_helper(config, url, username);
+        }
+        if allowed.contains(CredentialType::USERNAME) {
+            return Cred::username(username.unwrap_or("git"));
+        }
+        Cred::default()
+    });
+    callbacks
+}
+
+/** Fetch the remote and fast-forward to it. Returns whether the sheet changed.
+ * If both sides have changed, merge gets the local and the remote timesheet.json
+ * and returns the merged one, which is committed with both as parents.
+ */
+pub fn git_pull(
+    trk_dir: &Path,
+    sync: &SyncConfig,
+    merge: impl FnOnce(&str, &str) -> Result<String, TrkError>,
+) -> Result<bool, TrkError> {
+    let repo = trk_repo(trk_dir)?;
+    let Some(target) = sync_target(&repo, sync)? else {
+        return Ok(false);
+    };
+    register_merge_driver(&repo)?;
+    let config = repo.config()?;
+    let mut remote = repo.find_remote(&target.remote)?;
+    let mut options = FetchOptions::new();
+    options.remote_callbacks(callbacks(&config));
+    let refspec = format!("+refs/heads/{}:{}", target.branch, target.tracking());
+    remote.fetch(&[refspec], Some(&mut options), None)?;
+
+    /* Nothing pushed to the remote yet */
+    let Ok(tracking) = repo.find_reference(&target.tracking()) else {
+        return Ok(false);
+    };
+    let fetched = repo.reference_to_annotated_commit(&tracking)?;
+    let (analysis, _) = repo.merge_analysis(&[&fetched])?;
+    if analysis.is_up_to_date() {
+        return Ok(false);
+    }
+    if analysis.is_fast_forward() || analysis.is_unborn() {
+        repo.reference(&target.local, fetched.id(), true, "pull: fast-forward")?;
+    } else {
+        let ours = head_commit(&repo)?.ok_or_else(|| TrkError::Git(String::from("no HEAD")))?;
+        let theirs = repo.find_commit(fetched.id())?;
+        let merged = merge(&sheet_of(&repo, &ours)?, &sheet_of(&repo, &theirs)?)?;
+        let message = format!("merge {}/{}", target.remote, target.branch);
+        let commit = commit_sheet(&repo, &merged, &message, &[&ours, &theirs])?;
+        repo.reference(&target.local, commit, true, &format!("pull: {message}"))?;
+    }
+    repo.checkout_head(Some(git2::build::CheckoutBuilder::new().force()))?;
+    Ok(true)
+}
+
+pub fn git_push(trk_dir: &Path, sync: &SyncConfig) -> Result<(), TrkError> {
+    let repo = trk_repo(trk_dir)?;
+    let Some(target) = sync_target(&repo, sync)? else {
+        return Ok(());
+    };
+    /* The remote can refuse a single reference without failing the push */
+    let refused = Cell::new(None);
+    let config = repo.config()?;
+    let mut remote = repo.find_remote(&target.remote)?;
+    let mut callbacks = callbacks(&config);
+    callbacks.push_update_reference(|reference, status| {
+        if let Some(status) = status {
+            refused.set(Some(format!("{reference}: {status}")));
+        }
+        Ok(())
+    });
+    let mut options = PushOptions::new();
+    options.remote_callbacks(callbacks);
+    let refspec = format!("{}:refs/heads/{}", target.local, target.branch);
+    remote.push(&[refspec], Some(&mut options))?;
+    if let Some(refused) = refused.take() {
+        return Err(TrkError::Git(format!("push refused, {refused}")));
+    }
+    let pushed = repo.refname_to_id(&target.local)?;
+    repo.reference(&target.tracking(), pushed, true, "push")?;
+    Ok(())
+}
+
+/* How the local sheet relates to the remote one, as of the last sync */
+pub struct SyncStatus {
+    pub remote: String,
+    pub branch: String,
+    /// None if the remote branch was never fetched or pushed
+    pub ahead_behind: Option<(usize, usize)>,
+}
+
+impl fmt::Display for SyncStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let remote = format!("{}/{}", self.remote, self.branch);
+        match self.ahead_behind {
+            None => write!(f, "Not synced with {remote} yet."),
+            Some((0, 0)) => write!(f, "Up to date with {remote} as of the last sync."),
+            Some((ahead, behind)) => write!(
+                f,
+                "{ahead} change(s) ahead of and {behind} behind {remote} as of the last sync."
+            ),
+        }
+    }
+}
+
+/* Doesn't contact the remote, only compares with what the last sync brought */
+pub fn git_sync_status(trk_dir: &Path, sync: &SyncConfig) -> Result<Option<SyncStatus>, TrkError> {
+    let repo = trk_repo(trk_dir)?;
+    let Some(target) = sync_target(&repo, sync)? else {
+        return Ok(None);
+    };
+    let ahead_behind = match (
+        repo.refname_to_id(&target.local),
+        repo.refname_to_id(&target.tracking()),
+    ) {
+        (Ok(local), Ok(tracking)) => Some(repo.graph_ahead_behind(local, tracking)?),
+        _ => None,
+    };
+    Ok(Some(SyncStatus {
+        remote: target.remote,
+        branch: target.branch,
+        ahead_behind,
+    }))
+}
+
+/* user.name of the project's repository, or of the global git config */
+pub fn git_author(dir: &Path) -> Option<String> {
+    let config = match project_repo(dir) {
+        Ok(repo) => repo.config().ok()?,
+        Err(_) => git2::Config::open_default().ok()?,
+    };
+    config
+        .get_string("user.name")
+        .ok()
+        .filter(|name| !name.is_empty())
+}
+
+/** Hashes and committer times of the commits of the tracked repository on all local branches,
+ * oldest first. Only commits whose author "Name <email>" contains author.
+ */
+pub fn git_commits(
+    dir: &Path,
+    since: Option<u64>,
+    author: &str,
+) -> Result<Vec<(String, u64)>, TrkError> {
+    let repo = project_repo(dir)?;
+    let mut walk = repo.revwalk()?;
+    walk.set_sorting(Sort::TIME | Sort::REVERSE)?;
+    walk.push_glob("refs/heads/*")?;
+    /* A detached HEAD is not on any branch */
+    if repo.head().is_ok() {
+        walk.push_head()?;
+    }
+    let mut commits = Vec::new();
+    for oid in walk {
+        let commit = repo.find_commit(oid?)?;
+        let time = commit.time().seconds() as u64 * 1000;
+        let commit_author = commit.author();
+        let name = format!(
+            "{} <{}>",
+            commit_author.name().unwrap_or_default(),
+            commit_author.email().unwrap_or_default()
+        );
+        if since.is_none_or(|since| time >= since) && name.contains(author) {
+            commits.push((commit.id().to_string(), time));
+        }
+    }
+    Ok(commits)
+}
+
+/* Author, date and message of a commit of the tracked repository and the lines it changed */
+pub fn git_commit_details(dir: &Path, hash: &str) -> Result<CommitDetails, TrkError> {
+    let repo = project_repo(dir)?;
+    let commit = repo.revparse_single(hash)?.peel_to_commit()?;
+    let author = commit.author();
+    let mut details = CommitDetails {
+        author: format!(
+            "{} <{}>",
+            author.name().unwrap_or_default(),
+            author.email().unwrap_or_default()
+        ),
+        date: commit.time().seconds() as u64 * 1000,
+        subject: commit.summary().unwrap_or_default().to_string(),
+        body: commit.body().unwrap_or_default().trim().to_string(),
+        files: Vec::new(),
+        insertions: 0,
+        deletions: 0,
+    };
+    /* Like git log, merges have no changes of their own */
+    if commit.parent_count() > 1 {
+        return Ok(details);
+    }
+    let parent_tree = match commit.parent(0) {
+        Ok(parent) => Some(parent.tree()?),
+        Err(_) => None,
+    };
+    let diff = repo.diff_tree_to_tree(
+        parent_tree.as_ref(),
+        Some(&commit.tree()?),
+        Some(&mut DiffOptions::new()),
+    )?;
+    let stats = diff.stats()?;
+    details.insertions = stats.insertions() as u64;
+    details.deletions = stats.deletions() as u64;
+    details.files = diff
+        .deltas()
+        .filter_map(|delta| delta.new_file().path().or_else(|| delta.old_file().path()))
+        .map(|path| path.to_string_lossy().to_string())
+        .collect();
+    Ok(details)
+}
+
+/* Where git looks for the hooks of the project, honouring core.hooksPath */
+pub fn git_hooks_dir(dir: &Path) -> Result<PathBuf, TrkError> {
+    let repo = project_repo(dir)?;
+    let base = repo.workdir().unwrap_or_else(|| repo.path()).to_path_buf();
+    match repo.config()?.get_path("core.hooksPath") {
+        Ok(path) => Ok(base.join(path)),
+        Err(_) => Ok(repo.commondir().join("hooks")),
+    }
+}
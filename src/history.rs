@@ -0,0 +1,205 @@
+/* Undo and redo on top of the history of the internal .trk git repository.
+ *
+ * Every command which changes the sheet is one commit. Undo and redo are commits as well,
+ * which restore an earlier content of timesheet.json and name the commit they undo or redo
+ * in their subject ("undo <hash>: pause session"). Replaying the subjects from the oldest
+ * commit on gives the stack of undoable changes and the stack of redoable ones,
+ * so undo/redo state survives pulling the sheet onto another machine.
+ */
+
+use crate::error::TrkError;
+use crate::git::Revision;
+use crate::util::{system_timezone, ts_to_date};
+
+const UNDO: &str = "undo ";
+const REDO: &str = "redo ";
+
+/* What to restore for an undo or redo step */
+pub struct Step {
+    /// Revision whose timesheet.json becomes the current one
+    pub restore: String,
+    /// Commit message for the step
+    pub message: String,
+}
+
+/* The changes which can be undone and the undone ones which can be redone, last on top */
+struct Stacks<'a> {
+    done: Vec<&'a Revision>,
+    undone: Vec<&'a Revision>,
+}
+
+/* The hash named in an undo or redo subject */
+fn target(subject: &str, prefix: &str) -> Option<String> {
+    let rest = subject.strip_prefix(prefix)?;
+    let hash = rest.split(':').next()?.trim();
+    Some(hash.to_string())
+}
+
+/* Takes the history newest first, as returned by git_log_trk */
+fn stacks(history: &[Revision]) -> Stacks<'_> {
+    let mut stacks = Stacks {
+        done: Vec::new(),
+        undone: Vec::new(),
+    };
+    for revision in history.iter().rev() {
+        if let Some(hash) = target(&revision.subject, UNDO) {
+            if let Some(index) = stacks.done.iter().rposition(|r| r.hash == hash) {
+                stacks.undone.push(stacks.done.remove(index));
+            }
+        } else if let Some(hash) = target(&revision.subject, REDO) {
+            if let Some(index) = stacks.undone.iter().rposition(|r| r.hash == hash) {
+                stacks.done.push(stacks.undone.remove(index));
+            }
+        } else {
+            stacks.done.push(revision);
+            stacks.undone.clear();
+        }
+    }
+    stacks
+}
+
+/* Undo the last change by going back to the state before it */
+pub fn undo(history: &[Revision]) -> Result<Step, TrkError> {
+    let stacks = stacks(history);
+    /* The oldest commit initialised trk, there is nothing before it */
+    let oldest = history.last().map(|revision| revision.hash.as_str());
+    match stacks.done.last() {
+        Some(revision) if Some(revision.hash.as_str()) != oldest => Ok(Step {
+            restore: format!("{}~1", revision.hash),
+            message: format!("{UNDO}{}: {}", revision.hash, revision.subject),
+        }),
+        _ => Err(TrkError::InvalidArgument(String::from("Nothing to undo."))),
+    }
+}
+
+/* Redo the last undone change by going back to the state right after it */
+pub fn redo(history: &[Revision]) -> Result<Step, TrkError> {
+    let stacks = stacks(history);
+    match stacks.undone.last() {
+        Some(revision) => Ok(Step {
+            restore: revision.hash.clone(),
+            message: format!("{REDO}{}: {}", revision.hash, revision.subject),
+        }),
+        None => Err(TrkError::InvalidArgument(String::from("Nothing to redo."))),
+    }
+}
+
+/* The history as shown by trk log, with undone changes marked */
+pub fn log(history: &[Revision], count: Option<usize>) -> String {
+    let stacks = stacks(history);
+    history
+        .iter()
+        .take(count.unwrap_or(usize::MAX))
+        .map(|revision| {
+            let undone = if stacks.undone.iter().any(|r| r.hash == revision.hash) {
+                " (undone)"
+            } else {
+                ""
+            };
+            format!(
+                "{}  {}  {}{undone}\n",
+                &revision.hash[..revision.hash.len().min(8)],
+                ts_to_date(revision.time, system_timezone()),
+                revision.subject
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /* A history newest first, as git_log_trk returns it, which grows like the .trk repo */
+    struct Repo {
+        history: Vec<Revision>,
+    }
+
+    impl Repo {
+        fn new() -> Self {
+            let mut repo = Self {
+                history: Vec::new(),
+            };
+            repo.commit("init");
+            repo
+        }
+
+        fn commit(&mut self, subject: &str) -> String {
+            let hash = format!("{:040x}", self.history.len() + 1);
+            self.history.insert(
+                0,
+                Revision {
+                    hash: hash.clone(),
+                    time: 1_700_000_000_000,
+                    subject: subject.to_string(),
+                },
+            );
+            hash
+        }
+
+        /* What trk undo and trk redo do with a step */
+        fn step(
+            &mut self,
+            step: fn(&[Revision]) -> Result<Step, TrkError>,
+        ) -> Result<Step, TrkError> {
+            let step = step(&self.history)?;
+            self.commit(&step.message);
+            Ok(step)
+        }
+    }
+
+    fn is_nothing_to(result: Result<Step, TrkError>, what: &str) -> bool {
+        matches!(result, Err(TrkError::InvalidArgument(message)) if message == format!("Nothing to {what}."))
+    }
+
+    #[test]
+    fn a_new_change_clears_the_redo_stack() {
+        let mut repo = Repo::new();
+        let begin = repo.commit("begin session");
+        let pause = repo.commit("pause session");
+
+        let undone = repo.step(undo).unwrap();
+        assert_eq!(undone.restore, format!("{pause}~1"));
+        assert_eq!(undone.message, format!("undo {pause}: pause session"));
+        let redone = repo.step(redo).unwrap();
+        assert_eq!(redone.restore, pause);
+        assert!(is_nothing_to(redo(&repo.history), "redo"));
+
+        repo.step(undo).unwrap();
+        assert!(log(&repo.history, None).contains("pause session (undone)"));
+        repo.commit("note");
+        assert!(is_nothing_to(redo(&repo.history), "redo"));
+        assert!(!log(&repo.history, None).contains("(undone)"));
+
+        /* The change undone before the new one stays undone */
+        let undone = repo.step(undo).unwrap();
+        assert_eq!(
+            undone.message,
+            format!("undo {}: note", repo.history[1].hash)
+        );
+        assert_eq!(repo.step(undo).unwrap().restore, format!("{begin}~1"));
+    }
+
+    #[test]
+    fn undo_stops_at_the_initial_commit() {
+        let mut repo = Repo::new();
+        let begin = repo.commit("begin session");
+        repo.commit("end session");
+
+        /* trk undo 5 stops at the first error */
+        let undone = (0..5)
+            .map(|_| repo.step(undo))
+            .take_while(Result::is_ok)
+            .count();
+        assert_eq!(undone, 2);
+        assert_eq!(
+            repo.history[0].subject,
+            format!("undo {begin}: begin session")
+        );
+        assert!(is_nothing_to(undo(&repo.history), "undo"));
+        assert_eq!(repo.history.len(), 5);
+
+        /* Both can be redone again */
+        assert_eq!(repo.step(redo).unwrap().restore, begin);
+    }
+}
@@ -0,0 +1,301 @@
+/* Installation of the git hooks which tell trk about commits and branches.
+ *
+ * The hooks are compiled in. They go into the hooks directory of the project,
+ * which git puts elsewhere if core.hooksPath is set. Existing hooks are kept:
+ * trk's lines go right after the shebang between two marker lines, where an exit
+ * further down can't skip them, and can be updated and removed again without touching
+ * the rest of the hook.
+ */
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::error::TrkError;
+use crate::git::git_hooks_dir;
+
+const HOOKS: [(&str, &str); 2] = [
+    ("post-commit", include_str!("../post-commit")),
+    ("post-checkout", include_str!("../post-checkout")),
+];
+
+const BEGIN: &str = "# >>> trk >>>";
+const END: &str = "# <<< trk <<<";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookAction {
+    Install,
+    Uninstall,
+    Status,
+}
+
+enum State {
+    NotInstalled,
+    Current,
+    /// Installed by an older trk
+    Outdated,
+    /// Calls trk outside of the markers, probably copied by hand
+    ByHand,
+    /// The markers don't enclose one block, probably edited by hand
+    Unbalanced,
+}
+
+/* The marked block for a hook: the script without its shebang and header comment */
+fn block(script: &str) -> String {
+    let body = script.split_once("\n\n").map_or(script, |(_, body)| body);
+    format!("{BEGIN}\n{}\n{END}\n", body.trim_end())
+}
+
+/* The hook without trk's block, and the block if there is one.
+ * None if the markers don't enclose exactly one block.
+ */
+fn split(content: &str) -> Option<(String, Option<String>)> {
+    match (content.matches(BEGIN).count(), content.matches(END).count()) {
+        (0, 0) => return Some((content.to_string(), None)),
+        (1, 1) => {}
+        _ => return None,
+    }
+    let begin = content.find(BEGIN)?;
+    let end = begin + content[begin..].find(END)?;
+    let end = content[end..]
+        .find('\n')
+        .map_or(content.len(), |newline| end + newline + 1);
+    let rest = format!("{}{}", &content[..begin], &content[end..]);
+    Some((rest, Some(content[begin..end].to_string())))
+}
+
+/* The hook with trk's block right after the shebang */
+fn with_block(rest: &str, script: &str) -> String {
+    let (shebang, body) = rest.split_once('\n').unwrap_or((rest, ""));
+    format!("{shebang}\n{}{body}", block(script))
+}
+
+fn state(path: &Path, script: &str) -> Result<State, TrkError> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(State::NotInstalled),
+        Err(e) => return Err(e.into()),
+    };
+    let Some((rest, installed)) = split(&content) else {
+        return Ok(State::Unbalanced);
+    };
+    Ok(match installed {
+        _ if rest.contains("trk commit") || rest.contains("trk branch") => State::ByHand,
+        /* Blocks appended by an older trk could come after an exit */
+        Some(_) if content == with_block(&rest, script) => State::Current,
+        Some(_) => State::Outdated,
+        None => State::NotInstalled,
+    })
+}
+
+#[cfg(unix)]
+fn make_executable(path: &Path) -> Result<(), TrkError> {
+    use std::os::unix::fs::PermissionsExt;
+    let mut permissions = fs::metadata(path)?.permissions();
+    permissions.set_mode(permissions.mode() | 0o755);
+    fs::set_permissions(path, permissions)?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn make_executable(_path: &Path) -> Result<(), TrkError> {
+    Ok(())
+}
+
+fn install(path: &Path, script: &str) -> Result<String, TrkError> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::from("#!/bin/sh\n"),
+        Err(e) => return Err(e.into()),
+    };
+    /* trk's lines are shell, so they can only go into shell scripts */
+    let shebang = content.lines().next().unwrap_or_default();
+    let is_shell = shebang.strip_prefix("#!").is_some_and(|interpreter| {
+        interpreter.split_whitespace().any(|word| {
+            matches!(
+                word.rsplit('/').next(),
+                Some("sh" | "bash" | "dash" | "zsh" | "ksh")
+            )
+        })
+    });
+    if !is_shell {
+        return Ok(format!(
+            "not a shell script ({shebang}), call trk from it by hand"
+        ));
+    }
+    let Some((rest, _)) = split(&content) else {
+        return Ok(String::from("unbalanced trk markers, fix them by hand"));
+    };
+    fs::write(path, with_block(&rest, script))?;
+    make_executable(path)?;
+    Ok(String::from("installed"))
+}
+
+fn uninstall(path: &Path) -> Result<String, TrkError> {
+    let content = fs::read_to_string(path)?;
+    let Some((rest, _)) = split(&content) else {
+        return Ok(String::from("unbalanced trk markers, fix them by hand"));
+    };
+    /* Remove the hook if trk created it */
+    if rest
+        .lines()
+        .all(|line| line.is_empty() || line.starts_with("#!"))
+    {
+        fs::remove_file(path)?;
+    } else {
+        fs::write(path, rest)?;
+    }
+    Ok(String::from("removed"))
+}
+
+/** Install, remove or check the hooks of the repository dir is in. Returns one line per hook. */
+pub fn hooks(action: HookAction, dir: &Path) -> Result<String, TrkError> {
+    let dir = git_hooks_dir(dir)?;
+    if action == HookAction::Install {
+        fs::create_dir_all(&dir)?;
+    }
+    let mut report = String::new();
+    for (name, script) in HOOKS {
+        let path = dir.join(name);
+        let result = match (action, state(&path, script)?) {
+            (_, State::ByHand) => format!(
+                "calls trk outside of the {BEGIN} markers, remove those lines \
+                 and run trk hooks install"
+            ),
+            (_, State::Unbalanced) => {
+                format!("the {BEGIN} and {END} markers don't enclose one block, fix them by hand")
+            }
+            (HookAction::Install, State::Current) => String::from("already installed"),
+            (HookAction::Install, _) => install(&path, script)?,
+            (HookAction::Uninstall, State::Current | State::Outdated) => uninstall(&path)?,
+            (HookAction::Uninstall, _) => String::from("not installed"),
+            (HookAction::Status, State::NotInstalled) => String::from("not installed"),
+            (HookAction::Status, State::Current) => String::from("installed"),
+            (HookAction::Status, State::Outdated) => {
+                String::from("outdated, run trk hooks install")
+            }
+        };
+        report.push_str(&format!("{}: {result}\n", path.display()));
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    const POST_COMMIT: &str = HOOKS[0].1;
+
+    /* A hook file of its own for each test */
+    struct Hook(PathBuf);
+
+    impl Hook {
+        fn new(name: &str, content: Option<&str>) -> Self {
+            let dir = std::env::temp_dir().join(format!("trk-hooks-{}", std::process::id()));
+            fs::create_dir_all(&dir).unwrap();
+            let path = dir.join(name);
+            let _ = fs::remove_file(&path);
+            if let Some(content) = content {
+                fs::write(&path, content).unwrap();
+            }
+            Self(path)
+        }
+
+        fn content(&self) -> String {
+            fs::read_to_string(&self.0).unwrap()
+        }
+    }
+
+    impl Drop for Hook {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn existing_hooks_are_kept() {
+        let original = "#!/bin/bash\necho checking\nexit 0\n";
+        let hook = Hook::new("round-trip", Some(original));
+        assert!(matches!(
+            state(&hook.0, POST_COMMIT),
+            Ok(State::NotInstalled)
+        ));
+        assert_eq!(install(&hook.0, POST_COMMIT).unwrap(), "installed");
+
+        /* Before the exit, which would skip it at the end */
+        let content = hook.content();
+        assert!(content.starts_with(&format!("#!/bin/bash\n{BEGIN}\n")));
+        assert!(content.contains("trk commit"));
+        assert!(content.ends_with(&format!("{END}\necho checking\nexit 0\n")));
+        assert!(matches!(state(&hook.0, POST_COMMIT), Ok(State::Current)));
+
+        assert_eq!(uninstall(&hook.0).unwrap(), "removed");
+        assert_eq!(hook.content(), original);
+    }
+
+    #[test]
+    fn outdated_blocks_are_replaced() {
+        let appended = format!("#!/bin/sh\nexit 0\n{BEGIN}\ntrk commit HEAD\n{END}\n");
+        let hook = Hook::new("outdated", Some(&appended));
+        assert!(matches!(state(&hook.0, POST_COMMIT), Ok(State::Outdated)));
+        install(&hook.0, POST_COMMIT).unwrap();
+        let content = hook.content();
+        assert_eq!(content.matches(BEGIN).count(), 1);
+        assert!(!content.contains("trk commit HEAD"));
+        assert!(content.ends_with(&format!("{END}\nexit 0\n")));
+        assert!(matches!(state(&hook.0, POST_COMMIT), Ok(State::Current)));
+
+        /* A block in the right place with old lines */
+        let old = content.replace("git rev-parse HEAD", "git rev-parse --verify HEAD");
+        fs::write(&hook.0, old).unwrap();
+        assert!(matches!(state(&hook.0, POST_COMMIT), Ok(State::Outdated)));
+    }
+
+    #[test]
+    fn only_shell_scripts_get_trk_lines() {
+        let python = "#!/usr/bin/env python3\nprint('checking')\n";
+        let hook = Hook::new("python", Some(python));
+        assert!(install(&hook.0, POST_COMMIT)
+            .unwrap()
+            .starts_with("not a shell script (#!/usr/bin/env python3)"));
+        assert_eq!(hook.content(), python);
+    }
+
+    #[test]
+    fn hooks_created_by_trk_are_removed() {
+        let hook = Hook::new("created", None);
+        install(&hook.0, POST_COMMIT).unwrap();
+        assert!(hook.content().starts_with(&format!("#!/bin/sh\n{BEGIN}\n")));
+        uninstall(&hook.0).unwrap();
+        assert!(!hook.0.exists());
+        assert!(matches!(
+            state(&hook.0, POST_COMMIT),
+            Ok(State::NotInstalled)
+        ));
+    }
+
+    #[test]
+    fn unbalanced_markers_are_left_alone() {
+        let swapped = format!("#!/bin/sh\n{END}\necho checking\n{BEGIN}\n");
+        let hook = Hook::new("unbalanced", Some(&swapped));
+        assert!(matches!(state(&hook.0, POST_COMMIT), Ok(State::Unbalanced)));
+        assert!(install(&hook.0, POST_COMMIT)
+            .unwrap()
+            .starts_with("unbalanced"));
+        assert!(uninstall(&hook.0).unwrap().starts_with("unbalanced"));
+        assert_eq!(hook.content(), swapped);
+
+        assert!(split(&format!("{BEGIN}\n{END}\n{END}\n")).is_none());
+        assert!(split(&format!("#!/bin/sh\n{BEGIN}\n")).is_none());
+    }
+
+    #[test]
+    fn hand_copied_hooks_are_reported() {
+        let hook = Hook::new(
+            "by-hand",
+            Some("#!/bin/sh\ntrk commit \"$(git rev-parse HEAD)\"\n"),
+        );
+        assert!(matches!(state(&hook.0, POST_COMMIT), Ok(State::ByHand)));
+    }
+}
@@ -0,0 +1,450 @@
+/* Import of time tracked with other tools.
+ *
+ * Every tool exports intervals of work with a description, tags and maybe a project.
+ * Intervals which start on the same day and belong to the same project become one session,
+ * with the gaps between them as pauses and the descriptions as notes.
+ * Days, and the times of csv exports, are those of the timezone the sheet records in.
+ * Nothing is imported if any interval overlaps another one or an existing session.
+ */
+
+use std::fmt::Write;
+
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde::Deserialize;
+
+use crate::error::TrkError;
+use crate::sheet::session::{EventType, Session};
+use crate::sheet::timesheet::Timesheet;
+use crate::timespec::local;
+use crate::util::ts_to_date;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportFormat {
+    /// Output of `timew export`
+    Timewarrior,
+    /// Detailed report of Toggl Track as csv
+    TogglCsv,
+    /// Detailed report of Clockify as csv
+    ClockifyCsv,
+}
+
+/* One block of work as the other tool tracked it */
+struct Interval {
+    /// Where in the file it came from, for error messages
+    origin: String,
+    start: u64,
+    end: u64,
+    project: Option<String>,
+    tags: Vec<String>,
+    note: Option<String>,
+}
+
+impl Interval {
+    fn describe(&self, tz: Tz) -> String {
+        format!(
+            "{} ({} to {})",
+            self.origin,
+            ts_to_date(self.start, tz),
+            ts_to_date(self.end, tz)
+        )
+    }
+}
+
+#[derive(Deserialize)]
+struct TimewarriorInterval {
+    start: String,
+    end: Option<String>,
+    #[serde(default)]
+    tags: Vec<String>,
+    annotation: Option<String>,
+}
+
+/* Toggl and Clockify only differ in the case of some column names */
+#[derive(Deserialize)]
+struct CsvEntry {
+    #[serde(rename = "Project", default)]
+    project: String,
+    #[serde(rename = "Description", default)]
+    description: String,
+    #[serde(rename = "Tags", default)]
+    tags: String,
+    #[serde(rename = "Start date", alias = "Start Date")]
+    start_date: String,
+    #[serde(rename = "Start time", alias = "Start Time")]
+    start_time: String,
+    #[serde(rename = "End date", alias = "End Date")]
+    end_date: String,
+    #[serde(rename = "End time", alias = "End Time")]
+    end_time: String,
+}
+
+/* Date and time formats of the csv exports, depending on the settings of the account */
+const DATE_FORMATS: [&str; 4] = ["%Y-%m-%d", "%m/%d/%Y", "%d.%m.%Y", "%d/%m/%Y"];
+const TIME_FORMATS: [&str; 4] = ["%H:%M:%S", "%I:%M:%S %p", "%H:%M", "%I:%M %p"];
+
+fn non_empty(text: &str) -> Option<String> {
+    Some(text.trim().to_string()).filter(|text| !text.is_empty())
+}
+
+fn parse_timewarrior_time(time: &str, origin: &str) -> Result<u64, TrkError> {
+    NaiveDateTime::parse_from_str(time, "%Y%m%dT%H%M%SZ")
+        .map(|time| Utc.from_utc_datetime(&time).timestamp_millis() as u64)
+        .map_err(|e| TrkError::InvalidTime(format!("{origin}: '{time}': {e}")))
+}
+
+fn parse_timewarrior(content: &str) -> Result<Vec<Interval>, TrkError> {
+    let entries: Vec<TimewarriorInterval> = serde_json::from_str(content)
+        .map_err(|e| TrkError::InvalidArgument(format!("Not a timewarrior export: {e}")))?;
+    entries
+        .into_iter()
+        .enumerate()
+        .map(|(index, entry)| {
+            let origin = format!("interval {}", index + 1);
+            let end = entry.end.ok_or_else(|| {
+                TrkError::InvalidArgument(format!(
+                    "{origin} is still running, stop it before exporting."
+                ))
+            })?;
+            Ok(Interval {
+                start: parse_timewarrior_time(&entry.start, &origin)?,
+                end: parse_timewarrior_time(&end, &origin)?,
+                project: None,
+                tags: entry.tags,
+                note: entry.annotation.as_deref().and_then(non_empty),
+                origin,
+            })
+        })
+        .collect()
+}
+
+/* Wall clock time in tz from the separate date and time columns */
+fn parse_csv_time(date: &str, time: &str, origin: &str, tz: Tz) -> Result<u64, TrkError> {
+    let invalid = || TrkError::InvalidTime(format!("{origin}: '{date} {time}'"));
+    let date = DATE_FORMATS
+        .iter()
+        .find_map(|format| NaiveDate::parse_from_str(date.trim(), format).ok())
+        .ok_or_else(invalid)?;
+    let time = TIME_FORMATS
+        .iter()
+        .find_map(|format| NaiveTime::parse_from_str(time.trim(), format).ok())
+        .ok_or_else(invalid)?;
+    local(date.and_time(time), tz)
+        .map(|time| time.timestamp_millis() as u64)
+        .map_err(|e| TrkError::InvalidTime(format!("{origin}: {e}")))
+}
+
+fn parse_csv(content: &str, tz: Tz) -> Result<Vec<Interval>, TrkError> {
+    let invalid = |e: csv::Error| TrkError::InvalidArgument(format!("Not a csv export: {e}"));
+    let mut reader = csv::Reader::from_reader(content.as_bytes());
+    let headers = reader.headers().map_err(invalid)?.clone();
+    let mut intervals = Vec::new();
+    for record in reader.records() {
+        let record = record.map_err(invalid)?;
+        let origin = format!(
+            "line {}",
+            record.position().map_or(0, |position| position.line())
+        );
+        let entry: CsvEntry = record.deserialize(Some(&headers)).map_err(invalid)?;
+        intervals.push(Interval {
+            start: parse_csv_time(&entry.start_date, &entry.start_time, &origin, tz)?,
+            end: parse_csv_time(&entry.end_date, &entry.end_time, &origin, tz)?,
+            project: non_empty(&entry.project),
+            tags: entry.tags.split(',').filter_map(non_empty).collect(),
+            note: non_empty(&entry.description),
+            origin,
+        });
+    }
+    Ok(intervals)
+}
+
+/* Intervals which end before they start or overlap an earlier one.
+ * Takes the intervals sorted by start. */
+fn check_intervals(intervals: &[Interval], tz: Tz, report: &mut String) {
+    let mut latest: Option<&Interval> = None;
+    for interval in intervals {
+        if interval.end <= interval.start {
+            writeln!(
+                report,
+                "    {} does not end after it starts",
+                interval.describe(tz)
+            )
+            .unwrap();
+            continue;
+        }
+        match latest {
+            Some(previous) if interval.start < previous.end => writeln!(
+                report,
+                "    {} overlaps {}",
+                interval.describe(tz),
+                previous.describe(tz)
+            )
+            .unwrap(),
+            _ => {}
+        }
+        if latest.is_none_or(|previous| interval.end > previous.end) {
+            latest = Some(interval);
+        }
+    }
+}
+
+/* Intervals which overlap a session already in the sheet */
+fn check_sheet(intervals: &[Interval], sheet: &Timesheet, tz: Tz, report: &mut String) {
+    for (index, session) in sheet.sessions().iter().enumerate() {
+        let end = if session.is_running() {
+            u64::MAX
+        } else {
+            session.end
+        };
+        for interval in intervals {
+            if interval.start < end && session.start < interval.end {
+                writeln!(
+                    report,
+                    "    {} overlaps session {index} ({})",
+                    interval.describe(tz),
+                    session.summary(Some(tz))
+                )
+                .unwrap();
+            }
+        }
+    }
+}
+
+/* Same day and same project go into one session */
+fn same_session(previous: &Interval, interval: &Interval, tz: Tz) -> bool {
+    let day = |timestamp: u64| {
+        tz.timestamp_millis_opt(timestamp as i64)
+            .unwrap()
+            .date_naive()
+    };
+    day(previous.start) == day(interval.start) && previous.project == interval.project
+}
+
+/* A finalized session with a pause for each gap between the intervals */
+fn to_session(group: &[Interval], tz: Tz) -> Result<Session, TrkError> {
+    let mut session = Session::new(group[0].start);
+    session.set_timezone(tz);
+    let mut last_end = None;
+    for interval in group {
+        let start = interval.start;
+        if let Some(last_end) = last_end.filter(|last_end| start > *last_end) {
+            session.push_event(last_end, Some(last_end), None, EventType::Pause)?;
+            session.push_event(start, Some(start), None, EventType::Resume)?;
+        }
+        if let Some(note) = interval.note.clone() {
+            session.push_event(start, Some(start), Some(note), EventType::Note)?;
+            session.tag_last_event(interval.tags.clone());
+        }
+        session.add_tags(interval.tags.clone());
+        last_end = Some(interval.end);
+    }
+    if let Some(ref project) = group[0].project {
+        session.set_project(project.clone());
+    }
+    session.finalize(group[group.len() - 1].end)?;
+    session.validate()?;
+    Ok(session)
+}
+
+/** Read an export of another tool and add its intervals to the sheet as sessions.
+ * Returns the number of intervals and of sessions they became.
+ */
+pub fn import(
+    sheet: &mut Timesheet,
+    format: ImportFormat,
+    content: &str,
+) -> Result<(usize, usize), TrkError> {
+    let tz = sheet.timezone();
+    let mut intervals = match format {
+        ImportFormat::Timewarrior => parse_timewarrior(content)?,
+        ImportFormat::TogglCsv | ImportFormat::ClockifyCsv => parse_csv(content, tz)?,
+    };
+    intervals.sort_by_key(|interval| interval.start);
+
+    let mut report = String::new();
+    check_intervals(&intervals, tz, &mut report);
+    check_sheet(&intervals, sheet, tz, &mut report);
+    if !report.is_empty() {
+        return Err(TrkError::InvalidArgument(format!(
+            "Nothing imported:\n{}",
+            report.trim_end()
+        )));
+    }
+
+    let sessions = intervals
+        .chunk_by(|previous, interval| same_session(previous, interval, tz))
+        .map(|group| to_session(group, tz))
+        .collect::<Result<Vec<Session>, TrkError>>()?;
+    let imported = (intervals.len(), sessions.len());
+    sheet.merge_sessions(sessions);
+    Ok(imported)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    use crate::clock::FakeClock;
+    use crate::sheet::session::Event;
+
+    const MINUTE: u64 = 60 * 1000;
+
+    const TIMEWARRIOR: &str = r#"[
+        {"id": 2, "start": "20261012T080000Z", "end": "20261012T100000Z",
+            "tags": ["trk", "docs"], "annotation": "README"},
+        {"id": 1, "start": "20261012T103000Z", "end": "20261012T120000Z", "tags": ["trk"]}
+    ]"#;
+
+    const TOGGL: &str = "\
+User,Email,Client,Project,Task,Description,Billable,Start date,Start time,End date,End time,Duration,Tags,Amount ()
+tester,t@t,,trk,,Import,No,2026-10-12,08:00:00,2026-10-12,09:30:00,01:30:00,\"rust, cli\",
+tester,t@t,,website,,,No,2026-10-12,10:00:00,2026-10-12,11:00:00,01:00:00,,
+";
+
+    const CLOCKIFY: &str = "\
+Project,Client,Description,Task,User,Group,Email,Tags,Billable,Start Date,Start Time,End Date,End Time,Duration (h)
+trk,,Import,,tester,,t@t,,No,10/12/2026,08:00:00 AM,10/12/2026,09:00:00 AM,01:00:00
+trk,,Review,,tester,,t@t,,No,10/12/2026,09:15:00 AM,10/12/2026,10:00:00 AM,00:45:00
+";
+
+    fn sheet(tz: Tz) -> Timesheet {
+        let clock = Arc::new(FakeClock::new(at(tz, 1, 0, 0)));
+        let mut sheet = Timesheet::with_clock(String::from("tester"), clock.clone());
+        clock.set(at(tz, 13, 20, 0));
+        sheet.set_timezone(Some(tz));
+        sheet
+    }
+
+    fn at(tz: Tz, day: u32, hour: u32, minute: u32) -> u64 {
+        tz.with_ymd_and_hms(2026, 10, day, hour, minute, 0)
+            .unwrap()
+            .timestamp_millis() as u64
+    }
+
+    fn notes(session: &Session) -> Vec<Option<&str>> {
+        session.events().iter().map(Event::note).collect()
+    }
+
+    fn refused(result: Result<(usize, usize), TrkError>) -> String {
+        match result {
+            Err(TrkError::InvalidArgument(report) | TrkError::InvalidTime(report)) => report,
+            other => panic!("imported: {other:?}"),
+        }
+    }
+
+    #[test]
+    fn timewarrior_intervals_with_gaps_become_pauses() {
+        let mut sheet = sheet(Tz::UTC);
+        let imported = import(&mut sheet, ImportFormat::Timewarrior, TIMEWARRIOR).unwrap();
+        assert_eq!(imported, (2, 1));
+        let session = &sheet.sessions()[0];
+        assert_eq!(session.start, at(Tz::UTC, 12, 8, 0));
+        assert_eq!(session.end, at(Tz::UTC, 12, 12, 0));
+        assert_eq!(session.pause_time(sheet.now()), 30 * MINUTE);
+        assert_eq!(notes(session), [Some("README"), None, None]);
+        assert!(sheet.check().is_empty());
+    }
+
+    #[test]
+    fn malformed_timewarrior_exports() {
+        let mut sheet = sheet(Tz::UTC);
+        let running = r#"[{"start": "20261012T080000Z", "tags": []}]"#;
+        assert!(
+            refused(import(&mut sheet, ImportFormat::Timewarrior, running))
+                .contains("interval 1 is still running")
+        );
+        let csv = refused(import(&mut sheet, ImportFormat::Timewarrior, TOGGL));
+        assert!(csv.starts_with("Not a timewarrior export"), "{csv}");
+        let time = r#"[{"start": "2026-10-12 08:00", "end": "20261012T100000Z"}]"#;
+        assert!(refused(import(&mut sheet, ImportFormat::Timewarrior, time))
+            .starts_with("interval 1: '2026-10-12 08:00'"));
+        assert!(sheet.sessions().is_empty());
+    }
+
+    #[test]
+    fn toggl_projects_become_sessions_in_the_timezone_of_the_sheet() {
+        let berlin = Tz::Europe__Berlin;
+        let mut sheet = sheet(berlin);
+        assert_eq!(
+            import(&mut sheet, ImportFormat::TogglCsv, TOGGL).unwrap(),
+            (2, 2)
+        );
+        let [trk, website] = sheet.sessions() else {
+            panic!("not two sessions");
+        };
+        assert_eq!(trk.start, at(berlin, 12, 8, 0));
+        assert_eq!(trk.end, at(berlin, 12, 9, 30));
+        assert_eq!(trk.project(), Some("trk"));
+        assert_eq!(notes(trk), [Some("Import")]);
+        assert_eq!(website.start, at(berlin, 12, 10, 0));
+        assert_eq!(website.project(), Some("website"));
+        assert!(website.events().is_empty());
+        assert!(sheet.check().is_empty());
+    }
+
+    #[test]
+    fn malformed_toggl_exports() {
+        let mut sheet = sheet(Tz::UTC);
+        let missing = "Project,Description,Start date,Start time\ntrk,,2026-10-12,08:00:00\n";
+        assert!(refused(import(&mut sheet, ImportFormat::TogglCsv, missing))
+            .starts_with("Not a csv export"));
+        let time = TOGGL.replace("09:30:00", "25:99:00");
+        assert!(refused(import(&mut sheet, ImportFormat::TogglCsv, &time))
+            .starts_with("line 2: '2026-10-12 25:99:00'"));
+        assert!(sheet.sessions().is_empty());
+    }
+
+    #[test]
+    fn clockify_with_us_dates_and_12_hour_times() {
+        let mut sheet = sheet(Tz::UTC);
+        assert_eq!(
+            import(&mut sheet, ImportFormat::ClockifyCsv, CLOCKIFY).unwrap(),
+            (2, 1)
+        );
+        let session = &sheet.sessions()[0];
+        assert_eq!(session.start, at(Tz::UTC, 12, 8, 0));
+        assert_eq!(session.end, at(Tz::UTC, 12, 10, 0));
+        assert_eq!(session.pause_time(sheet.now()), 15 * MINUTE);
+        assert_eq!(notes(session), [Some("Import"), None, None, Some("Review")]);
+        assert!(sheet.check().is_empty());
+    }
+
+    #[test]
+    fn malformed_clockify_exports() {
+        let mut sheet = sheet(Tz::UTC);
+        let backwards = CLOCKIFY.replace("09:00:00 AM", "07:00:00 AM");
+        let report = refused(import(&mut sheet, ImportFormat::ClockifyCsv, &backwards));
+        assert!(
+            report.contains("line 2 (2026-10-12, 08:00 to 2026-10-12, 07:00) does not end"),
+            "{report}"
+        );
+        let date = CLOCKIFY.replace("10/12/2026,09:15", "Oct 12,09:15");
+        assert!(
+            refused(import(&mut sheet, ImportFormat::ClockifyCsv, &date))
+                .starts_with("line 3: 'Oct 12 09:15:00 AM'")
+        );
+        assert!(sheet.sessions().is_empty());
+    }
+
+    #[test]
+    fn overlaps_are_not_imported() {
+        let mut sheet = sheet(Tz::UTC);
+        let overlapping = CLOCKIFY.replace("09:15:00 AM", "08:45:00 AM");
+        let report = refused(import(&mut sheet, ImportFormat::ClockifyCsv, &overlapping));
+        assert!(
+            report.contains("line 3 (2026-10-12, 08:45 to 2026-10-12, 10:00) overlaps line 2"),
+            "{report}"
+        );
+        assert!(sheet.sessions().is_empty());
+
+        sheet.new_session(Some(at(Tz::UTC, 12, 9, 45))).unwrap();
+        sheet.end_session(Some(at(Tz::UTC, 12, 11, 0))).unwrap();
+        let report = refused(import(&mut sheet, ImportFormat::ClockifyCsv, CLOCKIFY));
+        assert!(
+            report.contains("line 3 (2026-10-12, 09:15 to 2026-10-12, 10:00) overlaps session 0"),
+            "{report}"
+        );
+        assert_eq!(sheet.sessions().len(), 1);
+    }
+}
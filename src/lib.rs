@@ -0,0 +1,36 @@
+/* trk as a library: timesheets of git based work, their storage in .trk and the reports.
+ *
+ * A Timesheet is the plain in-memory model, changed by methods like new_session or pause
+ * and (de)serialized with parse and to_json. A Storage is the directory containing .trk;
+ * it loads the sheet, saves it as a commit of the .trk repository and writes the reports.
+ *
+ *     let storage = Storage::discover(Path::new("."))?;
+ *     let mut sheet = storage.load()?.ok_or(TrkError::NotInitialised)?;
+ *     sheet.note(None, String::from("from the dashboard"), vec![])?;
+ *     storage.save(&sheet, "add note to session")?;
+ *
+ * The trk binary is a command line interface on top of this and nothing more.
+ */
+
+pub mod clock;
+pub mod config;
+pub mod doctor;
+pub mod error;
+pub mod export;
+pub mod git;
+pub mod history;
+pub mod hooks;
+pub mod import;
+pub mod sheet;
+pub mod storage;
+pub mod timespec;
+pub mod util;
+
+pub use clock::{Clock, FakeClock, SystemClock};
+pub use config::Config;
+pub use error::TrkError;
+pub use sheet::filter::Filter;
+pub use sheet::session::{CommitDetails, Event, EventType, Session};
+pub use sheet::timesheet::Timesheet;
+pub use sheet::traits::{HasHTML, HasTEX};
+pub use storage::Storage;
@@ -1,14 +1,22 @@
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueEnum};
 
-use crate::sheet::timesheet::Timesheet;
-use crate::util::{
-    get_seconds, git_commit_trk, git_pull, git_push, parse_hhmm_to_seconds, set_to_trk_dir,
-};
-use std::process;
+use chrono::{TimeZone, Utc};
+use chrono_tz::Tz;
 
-mod config;
-mod sheet;
-mod util;
+use std::path::{Path, PathBuf};
+use std::{env, fs, process};
+
+use trk::config::SyncMode;
+use trk::doctor::diagnose;
+use trk::export::{export, ExportFormat, Records};
+use trk::git::{git_author, git_commit_details, git_commits, limit_timeouts};
+use trk::history;
+use trk::hooks::{hooks, HookAction};
+use trk::import::{import, ImportFormat};
+use trk::timespec::{parse_time, parse_time_on};
+use trk::{EventType, Filter, HasTEX, Storage, Timesheet, TrkError};
+use url::Url;
+use url_open::UrlOpen;
 
 #[derive(Debug, clap::Parser)]
 #[clap(version, author, about)]
@@ -27,202 +35,898 @@ pub enum Command {
     },
     /// Begin session
     Begin {
-        /// Begin in the past, specify how long ago.  Time must be after the last event though.
+        /// Begin in the past: how long ago (90m, 1h30, 01:30) or when (yesterday 17:00,
+        /// 2026-10-01T09:00, last monday 9:00).  Time must be after the last event though.
         ago: Option<String>,
+        /// Project the session's work goes to
+        #[clap(long)]
+        project: Option<String>,
+        /// Tag the session, can be given several times
+        #[clap(long = "tag")]
+        tags: Vec<String>,
     },
     /// End session
     End {
-        /// End in the past, specify how long ago.  Time must be after the last event though.
+        /// End in the past: how long ago (90m, 1h30, 01:30) or when (yesterday 17:00,
+        /// 2026-10-01T09:00, last monday 9:00).  Time must be after the last event though.
         ago: Option<String>,
     },
-    /// Pause current session
+    /// Pause current session, or insert a forgotten pause into a past session
     Pause {
         /// Pause note
         note: Option<String>,
-        /// Begin pause in the past, specify how long ago.  Time must be after the last event though.
+        /// Begin pause in the past: how long ago (90m, 1h30, 01:30) or when (yesterday 17:00,
+        /// 2026-10-01T09:00, last monday 9:00).  Time must be after the last event though.
+        #[clap(conflicts_with = "session")]
         ago: Option<String>,
+        /// Insert the pause into this session (see `trk list`) instead, even if it has ended
+        #[clap(long, requires_all = ["from", "to"])]
+        session: Option<usize>,
+        /// Start of the inserted pause. HH:MM is the time on the day of the session
+        #[clap(long, requires = "session")]
+        from: Option<String>,
+        /// End of the inserted pause. HH:MM is the time on the day of the session
+        #[clap(long, requires = "session")]
+        to: Option<String>,
     },
     /// Resume currently paused session
     Resume {
-        /// Resume in the past, specify how long ago.  Time must be after the last event though.
+        /// Resume in the past: how long ago (90m, 1h30, 01:30) or when (yesterday 17:00,
+        /// 2026-10-01T09:00, last monday 9:00).  Time must be after the last event though.
         ago: Option<String>,
     },
     /// Add a note about current work or pause
     Note {
         /// Note content
         content: String,
-        /// Add a note in the past, specify how long ago.  Time must be after the last event though.
+        /// Add a note in the past: how long ago (90m, 1h30, 01:30) or when (yesterday 17:00,
+        /// 2026-10-01T09:00, last monday 9:00).  Time must be after the last event though.
         ago: Option<String>,
+        /// Tag the note, can be given several times
+        #[clap(long = "tag")]
+        tags: Vec<String>,
+    },
+    /// Set the project of a session or add tags to it
+    Tag {
+        /// Tags to add
+        tags: Vec<String>,
+        /// Project the session's work goes to
+        #[clap(long)]
+        project: Option<String>,
+        /// Session index as shown by `trk list`, the last session if not given
+        #[clap(long)]
+        session: Option<usize>,
+    },
+    /// List sessions, or the events of one session, with the indices used by edit and delete
+    List {
+        /// Session index
+        session: Option<usize>,
+    },
+    /// Change the time, note or type of a past event
+    Edit {
+        /// Session index as shown by `trk list`
+        #[clap(required_unless_present = "at")]
+        session: Option<usize>,
+        /// Event index as shown by `trk list <session>`
+        #[clap(required_unless_present = "at")]
+        event: Option<usize>,
+        /// Address the event by the minute it happened at instead (yesterday 12:03)
+        #[clap(long, conflicts_with_all = ["session", "event"])]
+        at: Option<String>,
+        /// New time of the event
+        #[clap(long)]
+        time: Option<String>,
+        /// New note, an empty note removes it
+        #[clap(long)]
+        note: Option<String>,
+        /// New type of the event
+        #[clap(long = "type", value_enum)]
+        kind: Option<EventKind>,
+    },
+    /// Delete an event, or a whole session if no event is given.
+    /// Deleting a pause or resume deletes the whole pause.
+    Delete {
+        /// Session index as shown by `trk list`
+        #[clap(required_unless_present = "at")]
+        session: Option<usize>,
+        /// Event index as shown by `trk list <session>`
+        event: Option<usize>,
+        /// Address the event by the minute it happened at instead (yesterday 12:03)
+        #[clap(long, conflicts_with_all = ["session", "event"])]
+        at: Option<String>,
+    },
+    /// Undo the last change to the sheet
+    Undo {
+        /// How many changes to undo
+        #[clap(default_value_t = 1)]
+        steps: usize,
+    },
+    /// Redo the last undone change
+    Redo {
+        /// How many changes to redo
+        #[clap(default_value_t = 1)]
+        steps: usize,
+    },
+    /// Show the history of changes to the sheet
+    Log {
+        /// Only show the last n changes
+        #[clap(short = 'n', long)]
+        count: Option<usize>,
+    },
+    /// Check the timesheet file for damage and broken sessions
+    Doctor {
+        /// Restore the newest revision without problems, like `trk restore`
+        #[clap(long)]
+        repair: bool,
+    },
+    /// Restore the sheet as it was after a change shown by `trk log`
+    Restore {
+        /// Revision of the internal git repository, like a hash from `trk log` or HEAD~3
+        revision: String,
     },
     /// Add a commit to the event list
     Commit {
         ///Commit hash id
         hash: String,
     },
+    /// Add the commits made during recorded sessions which the post-commit hook missed
+    Backfill {
+        /// Only commits after this time (one week ago, 2026-09-01, last monday)
+        #[clap(long)]
+        since: Option<String>,
+        /// Only commits whose author "Name <email>" contains this.
+        /// Default is the user name of the sheet.
+        #[clap(long)]
+        author: Option<String>,
+    },
     /// Add a branch to the session's branch list
     Branch {
         /// Branch name
         name: String,
     },
+    /// Install the git hooks which add commits and branches to the sheet, remove them again,
+    /// or check if they are installed and up to date
+    Hooks {
+        #[clap(value_enum)]
+        action: HookActionArg,
+    },
     /// Show information about git commits/branches in the report
     SetShowCommits {
         /// on_or_off
         on_off: bool,
     },
-    /// Set git repo url to use for turning commit hashes to links
+    /// Set git repo url to use for turning commit hashes to links.
+    /// GitHub, GitLab, Gitea, Forgejo and Bitbucket are recognised by their host name.
     SetRepoUrl {
-        /// url to repository
+        /// url to repository, or its ssh remote like git@github.com:user/repo.git
         url: String,
     },
+    /// Set how commits are linked for hosts which are not recognised, like {repo}/-/commit/{hash}
+    /// for a self-hosted GitLab. An empty template goes back to recognising the host.
+    SetCommitUrl {
+        /// Link with {repo} for the repository url and {hash} for the commit hash
+        template: String,
+    },
+    /// Set when the internal .trk repository is pulled from and pushed to its remote on this machine
+    SetSync {
+        #[clap(value_enum)]
+        mode: SyncModeArg,
+        /// Remote to sync with, instead of the upstream of the branch or the first remote.
+        /// An empty name goes back to that.
+        #[clap(long)]
+        remote: Option<String>,
+        /// Branch on the remote, instead of the one with the same name.
+        /// An empty name goes back to that.
+        #[clap(long)]
+        branch: Option<String>,
+    },
+    /// Pull and push the sheet now, whatever the sync mode, and show how it relates to the remote
+    Sync,
+    /// Set the timezone all sessions are shown and recorded in, like Europe/Berlin.
+    /// An empty name shows each session in the timezone it was recorded in again.
+    SetTimezone {
+        /// IANA name of the timezone
+        name: String,
+    },
     /// Prints the current WIP for session or sheet
     Status {
         /// Session or Sheet
         id: String,
+
+        /// Show days in this timezone (like UTC or America/New_York) instead of the configured one
+        #[clap(long, value_parser = parse_timezone)]
+        tz: Option<Tz>,
     },
-    /// Generate html report for current session or entire sheet and save it to {timesheet|session}.html
+    /// Generate report for current session or entire sheet and save it to {timesheet|session}.{html|tex}
     Report {
         /// Session or Sheet
         id: String,
 
-        /// How long the record should go back
+        /// How long the record should go back (one week, 30d) or since when
+        /// (since=2026-09-01, last monday). Sessions which started before count with the
+        /// time after it
         ago: Option<String>,
+
+        /// Output format. html is opened in the browser, tex is written for pdflatex
+        #[clap(long, value_enum, default_value_t = ReportFormat::Html)]
+        format: ReportFormat,
+
+        /// Only sessions of this project
+        #[clap(long)]
+        project: Option<String>,
+
+        /// Only sessions with this tag on the session or one of its events, can be given several times
+        #[clap(long = "tag")]
+        tags: Vec<String>,
+
+        /// Show times in this timezone (like UTC or America/New_York) instead of the configured one
+        #[clap(long, value_parser = parse_timezone)]
+        tz: Option<Tz>,
+    },
+    /// Write sessions and their events to stdout for spreadsheets and scripts.
+    /// The schema is described in the README.
+    Export {
+        /// csv has one row per session, or per event with --records events.
+        /// json and jsonl contain both.
+        #[clap(long, value_enum, default_value_t = ExportFormatArg::Csv)]
+        format: ExportFormatArg,
+
+        /// Which rows a csv export contains
+        #[clap(long, value_enum, default_value_t = RecordsArg::Sessions)]
+        records: RecordsArg,
+
+        /// Only sessions which ended after this time (one week ago, 2026-09-01, last monday),
+        /// also if they started before it
+        #[clap(long)]
+        since: Option<String>,
+
+        /// Only sessions which started before this time
+        #[clap(long)]
+        until: Option<String>,
+
+        /// Only sessions of this project
+        #[clap(long)]
+        project: Option<String>,
+
+        /// Only sessions with this tag on the session or one of its events, can be given several times
+        #[clap(long = "tag")]
+        tags: Vec<String>,
+    },
+    /// Add the time tracked with another tool to the sheet.
+    /// Intervals on the same day and project become one session, the gaps pauses.
+    Import {
+        /// Tool the file was exported from
+        #[clap(long = "from", value_enum)]
+        format: ImportFormatArg,
+
+        /// The exported file
+        file: PathBuf,
+    },
+    /// Merge another version of the sheet, like a timesheet.json copied from another machine.
+    /// Sessions with the same start are combined, overlapping ones are refused.
+    Merge {
+        /// The other timesheet.json
+        file: PathBuf,
+    },
+    /// Merge driver for git in .trk: merges other into current and writes the result to current
+    #[clap(hide = true)]
+    MergeDriver {
+        ancestor: PathBuf,
+        current: PathBuf,
+        other: PathBuf,
     },
     /// Temporary: clears all sessions and updates all timestamps
     Clear,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ReportFormat {
+    Html,
+    Tex,
+}
+
+/// Event types which can be set by `trk edit`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum EventKind {
+    Pause,
+    Resume,
+    Note,
+}
+
+impl From<EventKind> for EventType {
+    fn from(kind: EventKind) -> Self {
+        match kind {
+            EventKind::Pause => Self::Pause,
+            EventKind::Resume => Self::Resume,
+            EventKind::Note => Self::Note,
+        }
+    }
+}
+
+/// Actions of `trk hooks`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum HookActionArg {
+    Install,
+    Uninstall,
+    Status,
+}
+
+impl From<HookActionArg> for HookAction {
+    fn from(action: HookActionArg) -> Self {
+        match action {
+            HookActionArg::Install => Self::Install,
+            HookActionArg::Uninstall => Self::Uninstall,
+            HookActionArg::Status => Self::Status,
+        }
+    }
+}
+
+/// Sync modes which can be set by `trk set-sync`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SyncModeArg {
+    /// Only by trk sync
+    Off,
+    /// Pull before each command, push only by trk sync
+    PullOnly,
+    /// Push after each change, pull only by trk sync
+    PushOnly,
+    /// Pull before and push after each command
+    OnCommand,
+    /// Pull when a session begins and push when it ends
+    OnEndOfSession,
+}
+
+impl From<SyncModeArg> for SyncMode {
+    fn from(mode: SyncModeArg) -> Self {
+        match mode {
+            SyncModeArg::Off => Self::Off,
+            SyncModeArg::PullOnly => Self::PullOnly,
+            SyncModeArg::PushOnly => Self::PushOnly,
+            SyncModeArg::OnCommand => Self::OnCommand,
+            SyncModeArg::OnEndOfSession => Self::OnEndOfSession,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ExportFormatArg {
+    Csv,
+    Json,
+    Jsonl,
+}
+
+impl From<ExportFormatArg> for ExportFormat {
+    fn from(format: ExportFormatArg) -> Self {
+        match format {
+            ExportFormatArg::Csv => Self::Csv,
+            ExportFormatArg::Json => Self::Json,
+            ExportFormatArg::Jsonl => Self::Jsonl,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum RecordsArg {
+    Sessions,
+    Events,
+}
+
+impl From<RecordsArg> for Records {
+    fn from(records: RecordsArg) -> Self {
+        match records {
+            RecordsArg::Sessions => Self::Sessions,
+            RecordsArg::Events => Self::Events,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ImportFormatArg {
+    /// Output of `timew export`
+    Timewarrior,
+    /// Detailed report of Toggl Track as csv
+    TogglCsv,
+    /// Detailed report of Clockify as csv
+    ClockifyCsv,
+}
+
+impl From<ImportFormatArg> for ImportFormat {
+    fn from(format: ImportFormatArg) -> Self {
+        match format {
+            ImportFormatArg::Timewarrior => Self::Timewarrior,
+            ImportFormatArg::TogglCsv => Self::TogglCsv,
+            ImportFormatArg::ClockifyCsv => Self::ClockifyCsv,
+        }
+    }
+}
+
+/* IANA timezone names, like Europe/Berlin or UTC */
+fn parse_timezone(name: &str) -> Result<Tz, String> {
+    name.parse()
+        .map_err(|_| format!("'{name}' is not a known timezone, try one like Europe/Berlin or UTC"))
+}
+
+/** Turn an optional time argument into a timestamp. None means now.
+ * Clock times and dates are those of the timezone tz.
+ * Fails if the argument can't be parsed instead of silently using the current time.
+ */
+fn resolve_time(spec: Option<String>, tz: Tz) -> Result<Option<u64>, TrkError> {
+    spec.map(|spec| {
+        parse_time(&spec, Utc::now().with_timezone(&tz))
+            .map_err(|e| TrkError::InvalidTime(format!("'{spec}': {e}")))
+    })
+    .transpose()
+}
+
+/* Time within a past session, where HH:MM means a time on the day the session started */
+fn resolve_time_in_session(spec: &str, session_start: u64, tz: Tz) -> Result<u64, TrkError> {
+    let day = tz.timestamp_millis_opt(session_start as i64).unwrap();
+    parse_time_on(spec, day, Utc::now().with_timezone(&tz))
+        .map_err(|e| TrkError::InvalidTime(format!("'{spec}': {e}")))
+}
+
+/* Session and event index given directly or by --at */
+fn locate_event(
+    sheet: &Timesheet,
+    session: Option<usize>,
+    event: Option<usize>,
+    at: Option<String>,
+) -> Result<(usize, Option<usize>), TrkError> {
+    match (resolve_time(at, sheet.timezone())?, session) {
+        (Some(timestamp), _) => sheet
+            .find_event(timestamp)
+            .map(|(session, event)| (session, Some(event))),
+        (None, Some(session)) => Ok((session, event)),
+        (None, None) => Err(TrkError::InvalidArgument(String::from(
+            "Give a session and event index or --at.",
+        ))),
+    }
+}
+
+/* Whether the sync mode pulls before the command */
+fn pulls_before(mode: SyncMode, command: &Command) -> bool {
+    match mode {
+        SyncMode::PullOnly | SyncMode::OnCommand => true,
+        SyncMode::OnEndOfSession => matches!(command, Command::Begin { .. }),
+        SyncMode::Off | SyncMode::PushOnly => false,
+    }
+}
+
+/* Whether the sync mode pushes after a command changed the sheet */
+const fn pushes_after(mode: SyncMode, ends_session: bool) -> bool {
+    match mode {
+        SyncMode::PushOnly | SyncMode::OnCommand => true,
+        SyncMode::OnEndOfSession => ends_session,
+        SyncMode::Off | SyncMode::PullOnly => false,
+    }
+}
+
+/* Not being able to push is no reason to stop working either.
+ * The mode is read after the command, so it is the one the command leaves behind.
+ */
+fn push_sheet(storage: &Storage, ends_session: bool) {
+    let pushed = storage.sync_config().and_then(|sync| {
+        if pushes_after(sync.mode, ends_session) {
+            storage.push(&sync)
+        } else {
+            Ok(())
+        }
+    });
+    if let Err(e) = pushed {
+        eprintln!("Could not push: {e}");
+    }
+}
+
+/* The local and the remote timesheet.json as one */
+fn merge_json(ours: &str, theirs: &str) -> Result<String, TrkError> {
+    let merged = Timesheet::parse(ours)?.merge(Timesheet::parse(theirs)?)?;
+    Ok(merged.to_json())
+}
+
+/* Commit the sheet and bring the reports up to date, which may fail on a broken template */
+fn save(storage: &Storage, sheet: &Timesheet, message: &str) -> Result<(), TrkError> {
+    storage.save(sheet, message)?;
+    if let Err(e) = storage.write_reports(sheet) {
+        eprintln!("Could not write the html reports: {e}");
+    }
+    Ok(())
+}
+
+/* Make the sheet of an earlier revision the current one and commit that */
+fn restore_revision(storage: &Storage, revision: &str, message: &str) -> Result<(), TrkError> {
+    save(storage, &storage.revision(revision)?, message)
+}
+
+/* Show a report in the browser */
+fn open_report(path: &Path) {
+    match Url::parse(&format!("file://{}", path.display())) {
+        Ok(url) => url.open(),
+        Err(e) => println!("Couldn't open file: {e}"),
+    }
+}
+
+fn unknown_id(text: &str) -> TrkError {
+    TrkError::InvalidArgument(format!(
+        "What do you mean by {text}? Should be either 'sheet' or 'session'."
+    ))
+}
+
 fn main() {
     /* Handle command line arguments with clap */
     let arguments = Arguments::parse();
 
-    let sheet = Timesheet::load_from_file();
+    /* SAFETY: trk runs on this one thread and hasn't used libgit2 yet */
+    unsafe { limit_timeouts() };
+
+    let doctor = matches!(arguments.command, Command::Doctor { .. });
+    if let Err(e) = run(arguments) {
+        eprintln!("{e}");
+        if matches!(e, TrkError::Corrupt(..)) && !doctor {
+            eprintln!("\tRun 'trk doctor' to find the last good revision.");
+        }
+        process::exit(e.exit_code());
+    }
+}
 
-    /* Gets a value for config if supplied by user, or defaults to "default.conf" */
-    /* let config = matches.value_of("config").unwrap_or("default.conf");
-    println!("[UNUSED] Value for config: {}", config); */
+fn run(arguments: Arguments) -> Result<(), TrkError> {
+    /* Paths given as arguments are relative to where trk was called, not to the .trk directory */
+    let working_dir = env::current_dir()?;
 
-    /* Special case for init because t_sheet can and should be None before initialisation
+    /* Special case for init because the sheet can and should be None before initialisation
      * Also, check for .trk directory only after this */
     if let Command::Init { name } = arguments.command {
-        match sheet {
-            Some(..) => println!("Already initialised."),
-            None => match Timesheet::init(name) {
-                Some(..) => {
-                    println!("Init successful.");
-                    git_commit_trk("initialise trk");
-                }
-                None => println!("Could not initialize."),
-            },
+        if let Ok(storage) = Storage::discover(&working_dir) {
+            if storage.load()?.is_some() {
+                return Err(TrkError::AlreadyInitialised);
+            }
+        }
+        let name = name
+            .or_else(|| git_author(&working_dir))
+            .ok_or(TrkError::NoUserName)?;
+        let storage = Storage::new(&working_dir);
+        let sheet = Timesheet::new(name);
+        storage.init(&sheet)?;
+        if let Err(e) = storage.write_reports(&sheet) {
+            eprintln!("Could not write the html reports: {e}");
         }
-        return;
+        println!("Init successful.");
+        return Ok(());
     }
 
-    /* Set current dir to the next upper directory containing a .trk directory */
-    if !set_to_trk_dir() {
-        println!("Fatal: not a .trk directory (or subdirectory of one).");
-        process::exit(0);
+    /* The hooks belong to the project's git repository, trk need not be initialised */
+    if let Command::Hooks { action } = arguments.command {
+        print!("{}", hooks(action.into(), &working_dir)?);
+        return Ok(());
     }
 
-    /* Special case for clear because t_sheet can be None when clearing (corrupt file) */
-    if let Command::Clear = arguments.command {
-        println!("Clearing timesheet.");
-        Timesheet::clear();
-        git_commit_trk("Cleared timesheet");
-        return;
+    /* git runs the merge driver within .trk, on files outside the working tree */
+    if let Command::MergeDriver { current, other, .. } = arguments.command {
+        let merged = merge_json(&fs::read_to_string(&current)?, &fs::read_to_string(other)?)?;
+        fs::write(current, merged)?;
+        return Ok(());
     }
 
     /* Ignore commit or branch on uninitialised trk,
      * which occur when post-commit/post-checkout hooks run
      */
-    if matches!(arguments.command, Command::Commit { hash: _ })
-        || matches!(arguments.command, Command::Branch { name: _ })
+    let storage = Storage::discover(&working_dir);
+    if matches!(
+        arguments.command,
+        Command::Commit { .. } | Command::Branch { .. }
+    ) && !storage.as_ref().is_ok_and(Storage::is_init)
     {
-        match sheet {
-            Some(..) => {}
-            None => process::exit(0),
+        return Ok(());
+    }
+
+    /* The next upper directory containing a .trk directory */
+    let storage = storage?;
+
+    /* The sync settings belong to this machine and are not part of the sheet */
+    if let Command::SetSync {
+        mode,
+        remote,
+        branch,
+    } = arguments.command
+    {
+        return storage.set_sync(mode.into(), remote, branch);
+    }
+
+    /* Special case for doctor because it has to cope with a sheet which can't be loaded */
+    if let Command::Doctor { repair } = arguments.command {
+        let diagnosis = diagnose(&storage)?;
+        print!("{}", diagnosis.report());
+        return match (diagnosis.problems.len(), diagnosis.last_good) {
+            (0, _) => Ok(()),
+            (_, Some(revision)) if repair => {
+                restore_revision(
+                    &storage,
+                    &revision.hash,
+                    &format!("restore {}", revision.hash),
+                )?;
+                println!("Restored {}.", revision.hash);
+                Ok(())
+            }
+            (problems, last_good) => {
+                if last_good.is_some() {
+                    println!("Run 'trk doctor --repair' to restore it.");
+                }
+                Err(TrkError::Corrupt(format!("{problems} problem(s) found")))
+            }
+        };
+    }
+
+    /* Special case for clear because the sheet can be None when clearing (corrupt file) */
+    if let Command::Clear = arguments.command {
+        println!("Clearing timesheet.");
+        /* Try to keep the user name, the sheet may well be corrupt */
+        let name = storage
+            .load()
+            .ok()
+            .flatten()
+            .and_then(|sheet| sheet.user_name().map(str::to_string))
+            .or_else(|| git_author(storage.root()))
+            .ok_or(TrkError::NoUserName)?;
+        return save(&storage, &Timesheet::new(name), "Cleared timesheet");
+    }
+
+    /* Continue only if timesheet file exists */
+    let mut sheet = storage.load()?.ok_or(TrkError::NotInitialised)?;
+    let sync = storage.sync_config()?;
+
+    /* Syncing on request, where failing is an error */
+    if let Command::Sync = arguments.command {
+        storage.pull(&sync, merge_json)?;
+        storage.push(&sync)?;
+        match storage.sync_status(&sync)? {
+            Some(status) => println!("{status}"),
+            None => println!("The .trk repository has no remote to sync with."),
         }
+        return Ok(());
     }
 
-    /* Pull new changes first */
-    git_pull();
-    /* Unwrap the timesheet and continue only if timesheet file exists */
-    let mut sheet = if let Some(file) = sheet {
-        file
-    } else {
-        println!("No timesheet file! You might have to init first.");
-        return;
-    };
+    /* Pull new changes first. Not being able to sync is no reason to stop working. */
+    if pulls_before(sync.mode, &arguments.command) {
+        match storage.pull(&sync, merge_json) {
+            Ok(true) => sheet = storage.load()?.ok_or(TrkError::NotInitialised)?,
+            Ok(false) => {}
+            Err(e) => eprintln!("Could not pull: {e}"),
+        }
+    }
+    let ends_session = matches!(arguments.command, Command::End { .. });
+
+    if let Command::Status { tz: Some(tz), .. } | Command::Report { tz: Some(tz), .. } =
+        arguments.command
+    {
+        sheet.show_in(tz);
+    }
+    let tz = sheet.timezone();
 
     /* Variable to hold git commit message */
     let message = match arguments.command {
-        Command::Begin { ago } => {
-            let timestamp: Option<u64> =
-                parse_hhmm_to_seconds(&ago.unwrap_or_default()).map(|ago| get_seconds() - ago);
-            sheet.new_session(timestamp);
+        Command::Begin { ago, project, tags } => {
+            sheet.new_session(resolve_time(ago, tz)?)?;
+            sheet.tag_session(None, project, tags)?;
             "begin new session"
         }
         Command::End { ago } => {
-            let timestamp: Option<u64> =
-                parse_hhmm_to_seconds(&ago.unwrap_or_default()).map(|ago| get_seconds() - ago);
-            sheet.end_session(timestamp);
+            sheet.end_session(resolve_time(ago, tz)?)?;
             "end session"
         }
-        Command::Pause { note, ago } => {
-            let timestamp: Option<u64> =
-                parse_hhmm_to_seconds(&ago.unwrap_or_default()).map(|ago| get_seconds() - ago);
-            match note {
-                Some(note_text) => sheet.pause(timestamp, Some(note_text)),
-                None => sheet.pause(timestamp, None),
-            }
+        Command::Pause {
+            note,
+            ago,
+            session: None,
+            ..
+        } => {
+            sheet.pause(resolve_time(ago, tz)?, note)?;
             "pause session"
         }
+        Command::Pause {
+            note,
+            session: Some(session),
+            from,
+            to,
+            ..
+        } => {
+            let start = sheet.session(session)?.start;
+            let from = resolve_time_in_session(&from.unwrap_or_default(), start, tz)?;
+            let to = resolve_time_in_session(&to.unwrap_or_default(), start, tz)?;
+            sheet.insert_pause(session, from, to, note)?;
+            "insert pause into session"
+        }
         Command::Resume { ago } => {
-            let timestamp: Option<u64> =
-                parse_hhmm_to_seconds(&ago.unwrap_or_default()).map(|ago| get_seconds() - ago);
-            sheet.resume(timestamp);
+            sheet.resume(resolve_time(ago, tz)?)?;
             "resume session"
         }
-        Command::Note { content, ago } => {
-            let timestamp: Option<u64> =
-                parse_hhmm_to_seconds(&ago.unwrap_or_default()).map(|ago| get_seconds() - ago);
-            sheet.note(timestamp, content);
+        Command::Note { content, ago, tags } => {
+            sheet.note(resolve_time(ago, tz)?, content, tags)?;
             "add note to session"
         }
+        Command::Tag {
+            tags,
+            project,
+            session,
+        } => {
+            sheet.tag_session(session, project, tags)?;
+            "tag session"
+        }
+        Command::List { session } => {
+            match session {
+                Some(session) => print!("{}", sheet.list_events(session)?),
+                None => print!("{}", sheet.list_sessions()),
+            }
+            return Ok(());
+        }
+        Command::Edit {
+            session,
+            event,
+            at,
+            time,
+            note,
+            kind,
+        } => {
+            let (session, event) = locate_event(&sheet, session, event, at)?;
+            let event = event.ok_or_else(|| {
+                TrkError::InvalidArgument(String::from("Which event of the session?"))
+            })?;
+            /* An empty note removes the note */
+            let note = note.map(|note| Some(note).filter(|note| !note.is_empty()));
+            sheet.edit_event(
+                session,
+                event,
+                resolve_time(time, tz)?,
+                note,
+                kind.map(EventType::from),
+            )?;
+            "edit event"
+        }
+        Command::Delete { session, event, at } => match locate_event(&sheet, session, event, at)? {
+            (session, Some(event)) => {
+                sheet.delete_event(session, event)?;
+                "delete event"
+            }
+            (session, None) => {
+                sheet.delete_session(session)?;
+                "delete session"
+            }
+        },
+        Command::Undo { steps } | Command::Redo { steps } => {
+            let step = match arguments.command {
+                Command::Undo { .. } => history::undo,
+                _ => history::redo,
+            };
+            for _ in 0..steps {
+                let step = step(&storage.history()?)?;
+                restore_revision(&storage, &step.restore, &step.message)?;
+                println!("{}", step.message);
+            }
+            push_sheet(&storage, ends_session);
+            return Ok(());
+        }
+        Command::Log { count } => {
+            print!("{}", history::log(&storage.history()?, count));
+            return Ok(());
+        }
+        Command::Restore { revision } => {
+            let hash = storage.resolve(&revision)?;
+            restore_revision(&storage, &hash, &format!("restore {hash}"))?;
+            push_sheet(&storage, ends_session);
+            return Ok(());
+        }
         Command::Commit { hash } => {
-            sheet.add_commit(hash);
+            let details = match git_commit_details(storage.root(), &hash) {
+                Ok(details) => Some(details),
+                Err(e) => {
+                    println!("No details found for commit {hash}: {e}.");
+                    None
+                }
+            };
+            sheet.add_commit(hash, details)?;
             "add commit to session"
         }
+        Command::Backfill { since, author } => {
+            let author = author
+                .or_else(|| sheet.user_name().map(str::to_string))
+                .ok_or(TrkError::NoUserName)?;
+            let root = storage.root();
+            let commits = git_commits(root, resolve_time(since, tz)?, &author)?;
+            let backfill = sheet.backfill(commits, |hash| git_commit_details(root, hash).ok());
+            println!(
+                "Added {} commits. {} were already recorded, {} not made during a session \
+                 and {} made during a pause.",
+                backfill.inserted, backfill.recorded, backfill.outside, backfill.refused
+            );
+            "backfill commits"
+        }
         Command::Branch { name } => {
             sheet.add_branch(name);
             "add branch to branchlist"
         }
-        Command::Status { id } => {
+        Command::Status { id, .. } => {
             match id.as_str() {
                 "session" => println!("{}", sheet.last_session_status()),
-                "sheet" => println!("{}", sheet.timesheet_status()),
-                text => {
-                    println!(
-                        "What do you mean by {text}? Should be either 'sheet' or 'session'."
-                    );
+                "sheet" => {
+                    println!("{}", sheet.timesheet_status());
+                    match storage.sync_status(&sync) {
+                        Ok(Some(status)) => println!("{status}"),
+                        Ok(None) => {}
+                        Err(e) => eprintln!("Could not compare with the remote: {e}"),
+                    }
                 }
+                text => return Err(unknown_id(text)),
             }
-            return;
+            return Ok(());
         }
-        Command::Report { id, ago } => {
-            match id.as_str() {
-                "session" => sheet.report_last_session(),
-                "sheet" => {
-                    let timestamp: Option<u64> = parse_hhmm_to_seconds(&ago.unwrap_or_default())
-                        .map(|ago| get_seconds() - ago);
-                    sheet.report_sheet(timestamp);
+        Command::Report {
+            id,
+            ago,
+            format,
+            project,
+            tags,
+            ..
+        } => {
+            let filter = Filter {
+                since: resolve_time(ago, tz)?,
+                until: None,
+                project,
+                tags,
+            };
+            match (id.as_str(), format) {
+                ("session", ReportFormat::Html) => {
+                    open_report(&storage.write_session_html(&sheet)?)
+                }
+                ("sheet", ReportFormat::Html) => {
+                    open_report(&storage.write_sheet_html(&sheet, &filter)?);
+                    /* Leave complete sheet html */
+                    storage.write_sheet_html(&sheet, &Filter::default())?;
                 }
-                text => {
-                    println!(
-                        "What do you mean by {text}? Should be either 'sheet' or 'session'."
-                    );
+                ("session", ReportFormat::Tex) => {
+                    let path = storage.write_tex("session.tex", &sheet.last_session_tex()?)?;
+                    println!("Wrote {}.", path.display());
                 }
+                ("sheet", ReportFormat::Tex) => {
+                    sheet.select(filter);
+                    let tex = sheet.to_tex(sheet.now(), sheet.shown_in());
+                    let path = storage.write_tex("timesheet.tex", &tex)?;
+                    println!("Wrote {}.", path.display());
+                }
+                (text, _) => return Err(unknown_id(text)),
             }
-            return;
+            return Ok(());
+        }
+        Command::Export {
+            format,
+            records,
+            since,
+            until,
+            project,
+            tags,
+        } => {
+            let filter = Filter {
+                since: resolve_time(since, tz)?,
+                until: resolve_time(until, tz)?,
+                project,
+                tags,
+            };
+            return export(
+                &sheet,
+                &filter,
+                format.into(),
+                records.into(),
+                std::io::stdout().lock(),
+            );
+        }
+        Command::Import { format, file } => {
+            let content = fs::read_to_string(working_dir.join(file))?;
+            let (intervals, sessions) = import(&mut sheet, format.into(), &content)?;
+            println!("Imported {intervals} intervals as {sessions} sessions.");
+            "import sessions"
+        }
+        Command::Merge { file } => {
+            let other = Timesheet::parse(&fs::read_to_string(working_dir.join(file))?)?;
+            sheet = sheet.merge(other)?;
+            println!("Merged.");
+            "merge sheet"
         }
         Command::SetShowCommits { on_off } => {
             sheet.show_commits(on_off);
@@ -232,9 +936,27 @@ fn main() {
             sheet.set_repo_url(url);
             "set repo url"
         }
-        Command::Init { name: _ } | Command::Clear => unreachable!(),
+        Command::SetCommitUrl { template } => {
+            sheet.set_commit_url(template);
+            "set commit url"
+        }
+        Command::SetTimezone { name } => {
+            let timezone = match name.as_str() {
+                "" => None,
+                name => Some(parse_timezone(name).map_err(TrkError::InvalidArgument)?),
+            };
+            sheet.set_timezone(timezone);
+            "set timezone"
+        }
+        Command::Init { name: _ }
+        | Command::Hooks { .. }
+        | Command::MergeDriver { .. }
+        | Command::Doctor { .. }
+        | Command::SetSync { .. }
+        | Command::Clear
+        | Command::Sync => unreachable!(),
     };
-    sheet.write_files();
-    git_commit_trk(message);
-    git_push();
+    save(&storage, &sheet, message)?;
+    push_sheet(&storage, ends_session);
+    Ok(())
 }
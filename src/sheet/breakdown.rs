@@ -0,0 +1,228 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use chrono::{Datelike, Duration, NaiveDate, TimeZone};
+use chrono_tz::Tz;
+use minijinja::{context, Value};
+
+use crate::sheet::session::Session;
+use crate::util::{ms_to_hms_string, tex_escape};
+
+/** Work and pause time of a set of sessions grouped in different ways.
+ * Days are those of the timezone a session is shown in (see Session::timezone),
+ * a session which goes on past midnight counts for each day with the time on it.
+ * Weeks and months add up their days. A session on several branches
+ * or with several tags counts fully for each of them.
+ */
+#[derive(Default, Debug)]
+pub struct Breakdown {
+    days: BTreeMap<String, Times>,
+    weeks: BTreeMap<String, Times>,
+    months: BTreeMap<String, Times>,
+    projects: BTreeMap<String, Times>,
+    tags: BTreeMap<String, Times>,
+    branches: BTreeMap<String, Times>,
+}
+
+#[derive(Default, Debug, Clone, Copy)]
+struct Times {
+    work: u64,
+    pause: u64,
+}
+
+impl Times {
+    fn between(session: &Session, from: u64, to: u64, now: u64) -> Self {
+        Self {
+            work: session.work_time_between(from, to, now),
+            pause: session.pause_time_between(from, to, now),
+        }
+    }
+}
+
+const NO_PROJECT: &str = "(no project)";
+
+/* How many of the most recent days and weeks the status shows */
+const STATUS_DAYS: usize = 7;
+const STATUS_WEEKS: usize = 4;
+
+fn add(totals: &mut BTreeMap<String, Times>, key: String, times: Times) {
+    let total = totals.entry(key).or_default();
+    total.work += times.work;
+    total.pause += times.pause;
+}
+
+/* Midnight in tz. Where the clocks skip midnight for daylight saving time,
+ * the day starts at 01:00 instead. */
+fn day_start(date: NaiveDate, tz: Tz) -> u64 {
+    let midnight = date.and_hms_opt(0, 0, 0).unwrap();
+    tz.from_local_datetime(&midnight)
+        .earliest()
+        .or_else(|| {
+            tz.from_local_datetime(&(midnight + Duration::hours(1)))
+                .earliest()
+        })
+        .unwrap()
+        .timestamp_millis() as u64
+}
+
+/* The days from..to falls on in tz, each with the part of from..to on it.
+ * At least the day of from, also if nothing is left of from..to. */
+fn days(from: u64, to: u64, tz: Tz) -> Vec<(NaiveDate, u64, u64)> {
+    let mut days = Vec::new();
+    let mut date = tz.timestamp_millis_opt(from as i64).unwrap().date_naive();
+    let mut start = from;
+    loop {
+        let next = date.succ_opt().unwrap();
+        let end = day_start(next, tz).min(to);
+        days.push((date, start, end));
+        if end >= to {
+            return days;
+        }
+        date = next;
+        start = end;
+    }
+}
+
+impl Breakdown {
+    /* Only the time within window (see Filter::window) counts. Running sessions count up to now */
+    pub fn new<'a>(
+        sessions: impl Iterator<Item = &'a Session>,
+        window: (u64, u64),
+        now: u64,
+        tz: Option<Tz>,
+    ) -> Self {
+        let (since, until) = window;
+        let mut breakdown = Self::default();
+        for session in sessions {
+            let end = if session.is_running() {
+                now
+            } else {
+                session.end
+            };
+            let from = session.start.max(since);
+            let to = end.min(until);
+            for (date, start, end) in days(from, to, session.timezone(tz)) {
+                let times = Times::between(session, start, end, now);
+                let week = date.iso_week();
+                add(
+                    &mut breakdown.days,
+                    date.format("%Y-%m-%d").to_string(),
+                    times,
+                );
+                add(
+                    &mut breakdown.weeks,
+                    format!("{}-W{:02}", week.year(), week.week()),
+                    times,
+                );
+                add(
+                    &mut breakdown.months,
+                    date.format("%Y-%m").to_string(),
+                    times,
+                );
+            }
+            let times = Times::between(session, since, until, now);
+            add(
+                &mut breakdown.projects,
+                session.project().unwrap_or(NO_PROJECT).to_string(),
+                times,
+            );
+            for tag in session.all_tags() {
+                add(&mut breakdown.tags, tag.clone(), times);
+            }
+            for branch in session.branches() {
+                add(&mut breakdown.branches, branch.clone(), times);
+            }
+        }
+        breakdown
+    }
+
+    /* Title, css class and totals of each grouping.
+     * Empty ones are left out, as are projects if no session has one. */
+    fn groups(&self) -> Vec<(&'static str, &'static str, &BTreeMap<String, Times>)> {
+        [
+            ("Day", "", &self.days),
+            ("Week", "", &self.weeks),
+            ("Month", "", &self.months),
+            ("Project", "", &self.projects),
+            ("Tag", "", &self.tags),
+            ("Branch", "git_info", &self.branches),
+        ]
+        .into_iter()
+        .filter(|(_, _, totals)| totals.keys().any(|key| key != NO_PROJECT))
+        .collect()
+    }
+
+    /* Text tables for trk status, only with the most recent days and weeks */
+    pub fn status(&self) -> String {
+        let mut status = String::new();
+        for (title, _, totals) in self.groups() {
+            let skip = match title {
+                "Day" => totals.len().saturating_sub(STATUS_DAYS),
+                "Week" => totals.len().saturating_sub(STATUS_WEEKS),
+                _ => 0,
+            };
+            writeln!(&mut status, "Work time per {}:", title.to_lowercase()).unwrap();
+            for (key, times) in totals.iter().skip(skip) {
+                writeln!(
+                    &mut status,
+                    "    {key:<20} {}",
+                    ms_to_hms_string(times.work)
+                )
+                .unwrap();
+            }
+        }
+        status
+    }
+
+    /* What the breakdown section of timesheet.html gets */
+    pub fn html_context(&self) -> Value {
+        self.groups()
+            .into_iter()
+            .map(|(title, class, totals)| {
+                let rows = totals
+                    .iter()
+                    .map(|(key, times)| {
+                        context! {
+                            key,
+                            work_time => ms_to_hms_string(times.work),
+                            pause_time => ms_to_hms_string(times.pause),
+                        }
+                    })
+                    .collect::<Vec<Value>>();
+                context! { title, class, rows }
+            })
+            .collect()
+    }
+
+    /* One table per grouping for the LaTeX report */
+    pub fn to_tex(&self) -> String {
+        let mut tex = String::new();
+        for (title, class, totals) in self.groups() {
+            /* Branches are git info, hidden unless \showcommitstrue */
+            let git_info = !class.is_empty();
+            if git_info {
+                tex.push_str("\\ifshowcommits\n");
+            }
+            write!(
+                &mut tex,
+                "\\begin{{tabular}}{{@{{}}lll@{{}}}}\n{title} & Worked for & Paused for \\\\\n\\midrule\n"
+            )
+            .unwrap();
+            for (key, times) in totals {
+                writeln!(
+                    &mut tex,
+                    "{} & {} & {} \\\\",
+                    tex_escape(key),
+                    ms_to_hms_string(times.work),
+                    ms_to_hms_string(times.pause)
+                )
+                .unwrap();
+            }
+            tex.push_str("\\end{tabular}\n\n\\medskip\n");
+            if git_info {
+                tex.push_str("\\fi\n");
+            }
+        }
+        tex
+    }
+}
@@ -0,0 +1,35 @@
+use crate::sheet::session::Session;
+
+/** Selects the sessions which go into a report.
+ * The default filter selects all sessions.
+ */
+#[derive(Default, Debug)]
+pub struct Filter {
+    /// Only sessions which ended after this time, also if they started before it
+    pub since: Option<u64>,
+    /// Only sessions which started before this time
+    pub until: Option<u64>,
+    /// Only sessions of this project
+    pub project: Option<String>,
+    /// Only sessions which carry all of these tags, on the session or on one of its events
+    pub tags: Vec<String>,
+}
+
+impl Filter {
+    pub fn matches(&self, session: &Session) -> bool {
+        self.since.is_none_or(|since| session.last_moment() > since)
+            && self.until.is_none_or(|until| session.start < until)
+            && self
+                .project
+                .as_ref()
+                .is_none_or(|project| session.project() == Some(project.as_str()))
+            && self.tags.iter().all(|tag| session.has_tag(tag))
+    }
+
+    /** The time the totals of a report count, from since until until.
+     * Sessions which reach past it only count with the part within.
+     */
+    pub fn window(&self) -> (u64, u64) {
+        (self.since.unwrap_or(0), self.until.unwrap_or(u64::MAX))
+    }
+}
@@ -0,0 +1,49 @@
+/* Rendering of the html reports from templates.
+ *
+ * The default templates in templates/ are compiled in. A file of the same name in
+ * .trk/templates/ replaces the default, so the reports can be changed without rebuilding.
+ * Everything the templates insert is html escaped unless a template marks it |safe.
+ */
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use minijinja::{Environment, Error, ErrorKind, Value};
+
+use crate::error::TrkError;
+
+const DEFAULT_TEMPLATES: [(&str, &str); 3] = [
+    ("macros.html", include_str!("../../templates/macros.html")),
+    ("session.html", include_str!("../../templates/session.html")),
+    (
+        "timesheet.html",
+        include_str!("../../templates/timesheet.html"),
+    ),
+];
+
+fn load(dir: &Path, name: &str) -> Result<Option<String>, Error> {
+    match fs::read_to_string(dir.join(name)) {
+        Ok(template) => Ok(Some(template)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(DEFAULT_TEMPLATES
+            .iter()
+            .find(|(default, _)| *default == name)
+            .map(|(_, template)| template.to_string())),
+        Err(e) => Err(Error::new(
+            ErrorKind::InvalidOperation,
+            format!("could not read {}", dir.join(name).display()),
+        )
+        .with_source(e)),
+    }
+}
+
+/** Render one of the templates with the given context, preferring the ones in dir */
+pub fn render(dir: &Path, name: &str, context: Value) -> Result<String, TrkError> {
+    let dir = PathBuf::from(dir);
+    let mut environment = Environment::new();
+    environment.set_loader(move |name| load(&dir, name));
+    environment
+        .get_template(name)
+        .and_then(|template| template.render(context))
+        .map_err(|e| TrkError::Template(e.to_string()))
+}
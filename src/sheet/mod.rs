@@ -1,3 +1,7 @@
-mod session;
+pub mod breakdown;
+pub mod filter;
+pub(crate) mod html;
+mod schema;
+pub mod session;
 pub mod timesheet;
-mod traits;
+pub(crate) mod traits;
@@ -0,0 +1,88 @@
+/* Versions of the timesheet.json format.
+ *
+ * Every change which older files don't deserialize into gets a new version and a step
+ * in MIGRATIONS, which upgrades the JSON of the version before. Fields with a serde default
+ * need no step, but still raise the version, so older trk versions refuse the file
+ * instead of dropping the field when writing it again.
+ */
+
+use serde_json::Value;
+
+use crate::error::TrkError;
+
+pub const SCHEMA_VERSION: u64 = 3;
+
+/* MIGRATIONS[n] upgrades version n to n + 1 */
+const MIGRATIONS: [fn(&mut Value); SCHEMA_VERSION as usize] =
+    [unversioned, milliseconds, timezones];
+
+/* Files from before versioning, whose missing fields all have defaults */
+fn unversioned(_sheet: &mut Value) {}
+
+fn scale(value: Option<&mut Value>) {
+    if let Some(value) = value {
+        if let Some(seconds) = value.as_u64() {
+            *value = Value::from(seconds * 1000);
+        }
+    }
+}
+
+/* Version 1 counted in seconds and ended sessions one second after their last moment,
+ * to keep timestamps strictly increasing. Version 2 counts in milliseconds
+ * and ends sessions at their real end.
+ */
+fn milliseconds(sheet: &mut Value) {
+    scale(sheet.get_mut("start"));
+    scale(sheet.get_mut("end"));
+    let Some(sessions) = sheet.get_mut("sessions").and_then(Value::as_array_mut) else {
+        return;
+    };
+    for session in sessions {
+        let events = session.get_mut("events").and_then(Value::as_array_mut);
+        let mut last = 0;
+        for event in events.into_iter().flatten() {
+            last = event
+                .get("timestamp")
+                .and_then(Value::as_u64)
+                .unwrap_or(last);
+            scale(event.get_mut("timestamp"));
+            scale(event.pointer_mut("/ev_ty/Commit/details/date"));
+        }
+        let start = session.get("start").and_then(Value::as_u64).unwrap_or(0);
+        if let Some(end) = session.get_mut("end") {
+            if let Some(seconds) = end.as_u64() {
+                *end = Value::from(seconds.saturating_sub(1).max(start).max(last));
+            }
+        }
+        scale(session.get_mut("start"));
+        scale(session.get_mut("end"));
+    }
+}
+
+/* Version 3 records the timezone of each session and may configure one for the sheet.
+ * Sessions without one are shown in the timezone of the machine, as before.
+ */
+fn timezones(_sheet: &mut Value) {}
+
+/** Upgrade the JSON of a timesheet of any older version to SCHEMA_VERSION */
+pub fn migrate(sheet: &mut Value) -> Result<(), TrkError> {
+    let version = match sheet.get("schema_version") {
+        None => 0,
+        Some(version) => version.as_u64().ok_or_else(|| {
+            TrkError::Corrupt(format!("schema_version {version} is not a number"))
+        })?,
+    };
+    if version > SCHEMA_VERSION {
+        return Err(TrkError::Corrupt(format!(
+            "it has schema version {version}, this trk only knows up to {SCHEMA_VERSION}. \
+             Please update trk"
+        )));
+    }
+    for migration in &MIGRATIONS[version as usize..] {
+        migration(sheet);
+    }
+    if let Some(object) = sheet.as_object_mut() {
+        object.insert(String::from("schema_version"), Value::from(SCHEMA_VERSION));
+    }
+    Ok(())
+}
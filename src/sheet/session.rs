@@ -1,53 +1,223 @@
 use std::fmt::Write;
 
-/* For branch name dedup */
-use std::collections::HashSet;
+/* For branch name dedup, ordered to keep timesheet.json stable */
+use std::collections::BTreeSet;
 
+use chrono_tz::Tz;
+use minijinja::{context, Value};
 use serde::{Deserialize, Serialize};
 
-use crate::util::{get_seconds, sec_to_hms_string, ts_to_date};
+use crate::config::Config;
+use crate::error::TrkError;
+use crate::util::{ms_to_hms_string, system_timezone, tex_escape, ts_to_date};
 
-/* For process termination */
-use std::process;
+use crate::sheet::traits::{HasHTML, HasTEX};
 
-use crate::sheet::traits::HasHTML;
+/* Length of abbreviated commit hashes, as git shows them */
+const SHORT_HASH: usize = 7;
 
-#[derive(PartialEq, Eq, PartialOrd, Serialize, Deserialize, Debug)]
+#[derive(PartialEq, Eq, PartialOrd, Clone, Serialize, Deserialize, Debug)]
 pub enum EventType {
     Pause,
     Resume,
     Note,
-    Commit { hash: String },
+    Commit {
+        hash: String,
+        /// None for commits recorded before trk kept details, or if git didn't know the hash
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        details: Option<CommitDetails>,
+    },
 }
 
-#[derive(Serialize, Deserialize, Debug)]
-struct Event {
+/** What git knows about a commit, collected when `trk commit` runs */
+#[derive(PartialEq, Eq, PartialOrd, Clone, Serialize, Deserialize, Debug)]
+pub struct CommitDetails {
+    /// Name <email>
+    pub author: String,
+    /// Committer date, in milliseconds like all timestamps
+    pub date: u64,
+    pub subject: String,
+    pub body: String,
+    pub files: Vec<String>,
+    pub insertions: u64,
+    pub deletions: u64,
+}
+
+/* Number of commits and changed lines, shown as "14 commits, +820/-310 lines" */
+#[derive(Default, Debug, Clone, Copy)]
+pub struct CommitStats {
+    pub commits: usize,
+    pub insertions: u64,
+    pub deletions: u64,
+}
+
+impl std::ops::Add for CommitStats {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            commits: self.commits + other.commits,
+            insertions: self.insertions + other.insertions,
+            deletions: self.deletions + other.deletions,
+        }
+    }
+}
+
+impl std::iter::Sum for CommitStats {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::default(), |total, stats| total + stats)
+    }
+}
+
+impl std::fmt::Display for CommitStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let plural = if self.commits == 1 { "" } else { "s" };
+        write!(
+            f,
+            "{} commit{plural}, +{}/-{} lines",
+            self.commits, self.insertions, self.deletions
+        )
+    }
+}
+
+impl EventType {
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Pause => "Pause",
+            Self::Resume => "Resume",
+            Self::Note => "Note",
+            Self::Commit { .. } => "Commit",
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct Event {
     timestamp: u64,
     note: Option<String>,
     ev_ty: EventType,
+    #[serde(default, skip_serializing_if = "BTreeSet::is_empty")]
+    tags: BTreeSet<String>,
+}
+
+impl Event {
+    pub const fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    pub fn note(&self) -> Option<&str> {
+        self.note.as_deref()
+    }
+
+    pub const fn ev_ty(&self) -> &EventType {
+        &self.ev_ty
+    }
+
+    pub const fn tags(&self) -> &BTreeSet<String> {
+        &self.tags
+    }
+
+    /** Subject and body of a commit. Commits recorded before trk kept details
+     * have the whole message as their note.
+     */
+    pub fn commit_message(&self) -> Option<(&str, &str)> {
+        match self.ev_ty {
+            EventType::Commit {
+                details: Some(ref details),
+                ..
+            } => Some((&details.subject, &details.body)),
+            EventType::Commit { details: None, .. } => {
+                let message = self.note.as_deref().unwrap_or_default().trim();
+                let (subject, body) = message.split_once('\n').unwrap_or((message, ""));
+                Some((subject.trim(), body.trim()))
+            }
+            _ => None,
+        }
+    }
+
+    /* The same event recorded twice, see Session::merge */
+    fn same(&self, other: &Self) -> bool {
+        match (&self.ev_ty, &other.ev_ty) {
+            (EventType::Commit { hash, .. }, EventType::Commit { hash: other, .. }) => {
+                hash.starts_with(other.as_str()) || other.starts_with(hash.as_str())
+            }
+            (ty, other_ty) => self.timestamp == other.timestamp && ty.kind() == other_ty.kind(),
+        }
+    }
+
+    /* Notes during a pause are appended to it with <br>, so the parts are joined */
+    fn merge(&mut self, other: &Self) {
+        if let Some(note) = &other.note {
+            let joined = match self.note.take() {
+                Some(mut joined) => {
+                    for part in note.split("<br>") {
+                        if !joined.split("<br>").any(|known| known == part) {
+                            joined.push_str("<br>");
+                            joined.push_str(part);
+                        }
+                    }
+                    joined
+                }
+                None => note.clone(),
+            };
+            self.note = Some(joined);
+        }
+        self.tags.extend(other.tags.iter().cloned());
+        if let (
+            EventType::Commit { hash, details },
+            EventType::Commit {
+                hash: other_hash,
+                details: other_details,
+            },
+        ) = (&mut self.ev_ty, &other.ev_ty)
+        {
+            /* Keep the full hash and whichever details are known */
+            if other_hash.len() > hash.len() {
+                hash.clone_from(other_hash);
+            }
+            if details.is_none() {
+                details.clone_from(other_details);
+            }
+        }
+    }
+
+    const fn new(timestamp: u64, note: Option<String>, ev_ty: EventType) -> Self {
+        Self {
+            timestamp,
+            note,
+            ev_ty,
+            tags: BTreeSet::new(),
+        }
+    }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Clone, Serialize, Deserialize, Debug)]
 pub struct Session {
     pub start: u64,
     pub end: u64,
     running: bool,
-    branches: HashSet<String>,
+    branches: BTreeSet<String>,
     events: Vec<Event>,
+    #[serde(default)]
+    project: Option<String>,
+    #[serde(default)]
+    tags: BTreeSet<String>,
+    /// Where the session was recorded, None for sessions from before trk kept it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    timezone: Option<Tz>,
 }
 
 impl Session {
-    pub fn new(timestamp: Option<u64>) -> Self {
-        let timestamp = match timestamp {
-            Some(timestamp) => timestamp,
-            None => get_seconds(),
-        };
+    pub const fn new(timestamp: u64) -> Self {
         Self {
             start: timestamp,
-            end: timestamp + 1,
+            end: timestamp,
             running: true,
-            branches: HashSet::<String>::new(),
-            events: Vec::<Event>::new(),
+            branches: BTreeSet::new(),
+            events: Vec::new(),
+            project: None,
+            tags: BTreeSet::new(),
+            timezone: None,
         }
     }
 
@@ -58,70 +228,66 @@ impl Session {
     pub fn is_paused(&self) -> bool {
         self.events
             .last()
-            .map_or(false, |ev| ev.ev_ty == EventType::Pause)
+            .is_some_and(|ev| ev.ev_ty == EventType::Pause)
     }
 
-    // TODO: improve the session finalize/end logic
+    /* The end of a running session is its last event so far */
     pub fn update_end(&mut self) {
-        self.end = match self.events.len() {
-            0 => self.end,
-            n => &self.events[n - 1].timestamp + 1,
+        if let Some(last_ev) = self.events.last() {
+            self.end = last_ev.timestamp;
         }
     }
 
-    pub fn finalize(&mut self, timestamp: Option<u64>) {
-        let timestamp = timestamp.unwrap_or_else(get_seconds);
-        let is_valid_ts = match self.events.len() {
-            0 => timestamp > self.start,
-            n => {
-                let last_ev = &self.events[n - 1];
-                timestamp > last_ev.timestamp
-            }
-        };
-        if !is_valid_ts {
-            println!("That is not a valid timestamp!");
-            process::exit(0);
+    /* The time the next event may have at the earliest */
+    fn earliest_next(&self) -> u64 {
+        self.events
+            .last()
+            .map_or(self.start, |last_ev| last_ev.timestamp)
+    }
+
+    /** End the session at timestamp, which may be the time of its last event */
+    pub fn finalize(&mut self, timestamp: u64) -> Result<(), TrkError> {
+        if timestamp < self.earliest_next() {
+            return Err(TrkError::InvalidTimestamp);
         }
 
         if self.is_running() {
             if self.is_paused() {
-                self.push_event(Some(timestamp), None, EventType::Resume);
+                self.push_event(timestamp, Some(timestamp), None, EventType::Resume)?;
             }
             self.running = false;
-            self.end = timestamp + 1;
+            self.end = timestamp;
         }
+        Ok(())
     }
 
+    /** Add an event at timestamp, or now if there is none.
+     * Only a given timestamp is checked, it must not be before the last event.
+     * Events at the same time keep the order they were added in.
+     */
     pub fn push_event(
         &mut self,
+        now: u64,
         timestamp: Option<u64>,
         note: Option<String>,
         type_of_event: EventType,
-    ) -> bool {
+    ) -> Result<(), TrkError> {
         /* Cannot push if session is already finalized. */
         if !self.is_running() {
-            println!("Already finalized, cannot push event.");
-            return false;
+            return Err(TrkError::NotRunning);
         }
 
         let timestamp = match timestamp {
             None => {
-                let now = get_seconds();
                 self.end = now;
                 now
             }
             Some(timestamp) => {
-                let is_valid_ts = match self.events.len() {
-                    0 => timestamp > self.start,
-                    n => timestamp > self.events[n - 1].timestamp,
-                };
-                if is_valid_ts {
-                    self.end = timestamp + 1;
-                    timestamp
-                } else {
-                    println!("That timestamp is before the last event.");
-                    return false;
+                if timestamp < self.earliest_next() {
+                    return Err(TrkError::InvalidTimestamp);
                 }
+                self.end = timestamp;
+                timestamp
             }
         };
         /* TODO: improve logic */
@@ -129,29 +295,17 @@ impl Session {
             // TODO: fix this, so both note and ago work...
             EventType::Pause => {
                 if self.is_paused() {
-                    println!("Already paused.");
-                    false
-                } else {
-                    self.events.push(Event {
-                        timestamp,
-                        note,
-                        ev_ty: EventType::Pause,
-                    });
-                    true
+                    return Err(TrkError::AlreadyPaused);
                 }
+                self.events
+                    .push(Event::new(timestamp, note, EventType::Pause));
             }
             EventType::Resume => {
-                if self.is_paused() {
-                    self.events.push(Event {
-                        timestamp,
-                        note,
-                        ev_ty: EventType::Resume,
-                    });
-                    true
-                } else {
-                    println!("Currently not paused.");
-                    false
+                if !self.is_paused() {
+                    return Err(TrkError::NotPaused);
                 }
+                self.events
+                    .push(Event::new(timestamp, note, EventType::Resume));
             }
             EventType::Note => {
                 if self.is_paused() {
@@ -159,89 +313,486 @@ impl Session {
                     /* If self.is_paused(), then self.len() is always at least 1 */
                     let len = self.events.len();
                     let pause = &mut self.events[len - 1];
-                    match pause.note {
-                        Some(ref mut already) => {
+                    match (&mut pause.note, note) {
+                        (Some(already), Some(note)) => {
                             already.push_str("<br>");
-                            already.push_str(&note.unwrap());
+                            already.push_str(&note);
                         }
-                        None => pause.note = note,
+                        (None, note) => pause.note = note,
+                        (Some(_), None) => {}
                     }
                 } else {
-                    self.events.push(Event {
-                        timestamp,
-                        note,
-                        ev_ty: EventType::Note,
-                    });
+                    self.events
+                        .push(Event::new(timestamp, note, EventType::Note));
                 };
-                true
             }
             /* Commit adding possible only in present */
-            EventType::Commit { hash } => {
+            EventType::Commit { hash, details } => {
                 if self.is_paused() {
-                    self.push_event(None, None, EventType::Resume);
-                }
-                /* Commit message must be provided */
-                if note.is_none() {
-                    println!("No commit message found for commit {hash}.");
+                    self.push_event(now, None, None, EventType::Resume)?;
                 }
-                self.events.push(Event {
-                    timestamp: get_seconds(),
-                    note,
-                    ev_ty: EventType::Commit { hash },
-                });
-                true
+                self.events
+                    .push(Event::new(now, note, EventType::Commit { hash, details }));
             }
         }
+        Ok(())
     }
 
-    pub fn pause_time(&self) -> u64 {
-        let mut pause_time = 0;
+    /* Running sessions and pauses last until now */
+    /* The pauses as (start, end), a pause which isn't over yet lasts until now */
+    fn pauses(&self, now: u64) -> Vec<(u64, u64)> {
+        let mut pauses = Vec::new();
         let mut last_pause_ts = 0;
         for event in &self.events {
             match event.ev_ty {
                 EventType::Pause => last_pause_ts = event.timestamp,
-                EventType::Resume => pause_time += event.timestamp - last_pause_ts,
+                EventType::Resume => pauses.push((last_pause_ts, event.timestamp)),
                 _ => {}
             }
         }
         if self.is_paused() {
-            pause_time += get_seconds() - self.events.last().unwrap().timestamp;
+            pauses.push((self.events.last().unwrap().timestamp, now));
         }
-        pause_time
+        pauses
     }
 
-    pub fn work_time(&self) -> u64 {
-        let pause_time = self.pause_time();
-        if self.is_running() {
-            get_seconds() - self.start - pause_time
+    pub fn pause_time(&self, now: u64) -> u64 {
+        self.pause_time_between(0, u64::MAX, now)
+    }
+
+    pub fn work_time(&self, now: u64) -> u64 {
+        self.work_time_between(0, u64::MAX, now)
+    }
+
+    /* Where the session ends, or now while it is running */
+    const fn end_at(&self, now: u64) -> u64 {
+        if self.running {
+            now
         } else {
-            self.end - self.start - pause_time
+            self.end
         }
     }
 
+    /** The part of the pause time which falls between from and to,
+     * like the pauses of one day of a session which goes on past midnight.
+     * Pauses only count within the session, so a pause of a running session which
+     * ends after now, recorded by a machine whose clock is ahead, counts up to now.
+     */
+    pub fn pause_time_between(&self, from: u64, to: u64, now: u64) -> u64 {
+        let window = (from.max(self.start), to.min(self.end_at(now)));
+        self.pauses(now)
+            .into_iter()
+            .map(|pause| overlap(pause, window))
+            .sum()
+    }
+
+    /** The part of the work time which falls between from and to */
+    pub fn work_time_between(&self, from: u64, to: u64, now: u64) -> u64 {
+        overlap((self.start, self.end_at(now)), (from, to))
+            .saturating_sub(self.pause_time_between(from, to, now))
+    }
+
+    pub fn commit_stats(&self) -> CommitStats {
+        self.events
+            .iter()
+            .filter_map(|event| match event.ev_ty {
+                EventType::Commit { ref details, .. } => Some(CommitStats {
+                    commits: 1,
+                    insertions: details.as_ref().map_or(0, |details| details.insertions),
+                    deletions: details.as_ref().map_or(0, |details| details.deletions),
+                }),
+                _ => None,
+            })
+            .sum()
+    }
+
     pub fn add_branch(&mut self, name: String) {
         if self.is_running() {
             self.branches.insert(name);
         }
     }
 
-    pub fn status(&self) -> String {
+    pub fn project(&self) -> Option<&str> {
+        self.project.as_deref()
+    }
+
+    pub fn set_project(&mut self, project: String) {
+        self.project = Some(project);
+    }
+
+    pub fn set_timezone(&mut self, timezone: Tz) {
+        self.timezone = Some(timezone);
+    }
+
+    /** The timezone the session is shown in: shown_in if given (by --tz or the config),
+     * else the one it was recorded in, else the one of this machine.
+     */
+    pub fn timezone(&self, shown_in: Option<Tz>) -> Tz {
+        shown_in.or(self.timezone).unwrap_or_else(system_timezone)
+    }
+
+    pub fn add_tags(&mut self, tags: Vec<String>) {
+        self.tags.extend(tags);
+    }
+
+    /* Tags of a note go to the event it ended up in, which is the pause if paused */
+    pub fn tag_last_event(&mut self, tags: Vec<String>) {
+        if let Some(event) = self.events.last_mut() {
+            event.tags.extend(tags);
+        }
+    }
+
+    /* Tags of the session and of its events */
+    pub fn all_tags(&self) -> BTreeSet<&String> {
+        self.tags
+            .iter()
+            .chain(self.events.iter().flat_map(|event| event.tags.iter()))
+            .collect()
+    }
+
+    pub fn events(&self) -> &[Event] {
+        &self.events
+    }
+
+    pub const fn tags(&self) -> &BTreeSet<String> {
+        &self.tags
+    }
+
+    pub fn branches(&self) -> impl Iterator<Item = &String> {
+        self.branches.iter()
+    }
+
+    /* Tagged directly or through one of its events */
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.contains(tag) || self.events.iter().any(|event| event.tags.contains(tag))
+    }
+
+    /* Project and tags for status output, one line each */
+    fn labels(&self) -> String {
+        let mut labels = String::new();
+        if let Some(ref project) = self.project {
+            writeln!(&mut labels, "    Project: {project}").unwrap();
+        }
+        if !self.tags.is_empty() {
+            let tags = self.tags.iter().cloned().collect::<Vec<String>>();
+            writeln!(&mut labels, "    Tags: {}", tags.join(", ")).unwrap();
+        }
+        labels
+    }
+
+    /** Check the invariants push_event maintains: events not before the
+     * session start or the event before them, not after the end of a finalized session,
+     * pauses and resumes alternating, no commits during a pause and no empty notes.
+     */
+    pub fn validate(&self) -> Result<(), TrkError> {
+        let inconsistent = |index: usize, problem: &str| {
+            Err(TrkError::Inconsistent(format!("event {index} {problem}")))
+        };
+        let mut last = self.start;
+        let mut paused = false;
+        for (index, event) in self.events.iter().enumerate() {
+            if event.timestamp < last {
+                return inconsistent(index, "is before the previous event");
+            }
+            last = event.timestamp;
+            match event.ev_ty {
+                EventType::Pause if paused => {
+                    return inconsistent(index, "pauses an already paused session")
+                }
+                EventType::Pause => paused = true,
+                EventType::Resume if !paused => {
+                    return inconsistent(index, "resumes a session which is not paused")
+                }
+                EventType::Resume => paused = false,
+                EventType::Note if event.note.is_none() => {
+                    return inconsistent(index, "is a note without text")
+                }
+                EventType::Commit { .. } if paused => {
+                    return inconsistent(index, "is a commit during a pause")
+                }
+                EventType::Note | EventType::Commit { .. } => {}
+            }
+        }
+        if !self.is_running() {
+            if last > self.end {
+                return Err(TrkError::Inconsistent(String::from(
+                    "events after the end of the session",
+                )));
+            }
+            if paused {
+                return Err(TrkError::Inconsistent(String::from(
+                    "the session ends while paused",
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /* Insert at the place given by the timestamp, validate() decides if that was legal */
+    fn insert_event(&mut self, event: Event) {
+        let index = self
+            .events
+            .partition_point(|ev| ev.timestamp <= event.timestamp);
+        self.events.insert(index, event);
+    }
+
+    /** Insert a pause into a session after the fact, like a forgotten lunch break.
+     * The pause must lie within the session and the past,
+     * and must not overlap another pause or contain a commit.
+     */
+    pub fn insert_pause(
+        &mut self,
+        from: u64,
+        to: u64,
+        note: Option<String>,
+        now: u64,
+    ) -> Result<(), TrkError> {
+        let problem = if from >= to {
+            Some("the pause has to end after it starts")
+        } else if from < self.start {
+            Some("the pause starts before the session")
+        } else if !self.running && to > self.end {
+            Some("the pause ends after the session")
+        } else if to > now {
+            Some("the pause ends in the future")
+        } else {
+            None
+        };
+        if let Some(problem) = problem {
+            return Err(TrkError::Inconsistent(String::from(problem)));
+        }
+        let mut edited = self.clone();
+        edited.insert_event(Event::new(from, note, EventType::Pause));
+        edited.insert_event(Event::new(to, None, EventType::Resume));
+        edited.validate()?;
+        *self = edited;
+        Ok(())
+    }
+
+    /** Insert a commit made during the session which the post-commit hook missed.
+     * Commits during a pause are refused.
+     */
+    pub fn insert_commit(
+        &mut self,
+        timestamp: u64,
+        hash: String,
+        details: Option<CommitDetails>,
+    ) -> Result<(), TrkError> {
+        let mut edited = self.clone();
+        edited.insert_event(Event::new(
+            timestamp,
+            None,
+            EventType::Commit { hash, details },
+        ));
+        edited.validate()?;
+        *self = edited;
+        Ok(())
+    }
+
+    /* Also finds commits recorded with an abbreviated hash */
+    pub fn has_commit(&self, hash: &str) -> bool {
+        self.events.iter().any(|event| match event.ev_ty {
+            EventType::Commit {
+                hash: ref recorded, ..
+            } => recorded.starts_with(hash) || hash.starts_with(recorded.as_str()),
+            _ => false,
+        })
+    }
+
+    /* A running session contains everything from its start on */
+    pub fn contains(&self, timestamp: u64) -> bool {
+        timestamp >= self.start && timestamp <= self.last_moment()
+    }
+
+    /* A running session has no end yet */
+    pub const fn last_moment(&self) -> u64 {
+        if self.running {
+            u64::MAX
+        } else {
+            self.end
+        }
+    }
+
+    /* Sessions where one ends when the other starts don't overlap */
+    pub const fn overlaps(&self, other: &Self) -> bool {
+        self.start < other.last_moment() && other.start < self.last_moment()
+    }
+
+    /** Combine two versions of the same session, recorded on different machines.
+     * The events are the union of both. Commits with the same hash are the same event,
+     * as are other events of the same type at the same time, whose notes are joined.
+     * Ended on either machine means ended. The result has to pass validate().
+     */
+    pub fn merge(&self, other: &Self) -> Result<Self, TrkError> {
+        let mut merged = self.clone();
+        for event in &other.events {
+            match merged.events.iter_mut().find(|known| known.same(event)) {
+                Some(known) => known.merge(event),
+                None => merged.insert_event(event.clone()),
+            }
+        }
+        merged.branches.extend(other.branches.iter().cloned());
+        merged.tags.extend(other.tags.iter().cloned());
+        if merged.project.is_none() {
+            merged.project.clone_from(&other.project);
+        }
+        match (self.running, other.running) {
+            (true, false) => {
+                merged.running = false;
+                merged.end = other.end;
+            }
+            (false, true) => {}
+            _ => merged.end = self.end.max(other.end),
+        }
+        merged.validate()?;
+        Ok(merged)
+    }
+
+    fn check_event_index(&self, index: usize) -> Result<(), TrkError> {
+        if index < self.events.len() {
+            Ok(())
+        } else {
+            Err(TrkError::InvalidArgument(format!(
+                "No event {index}, the session has {} events.",
+                self.events.len()
+            )))
+        }
+    }
+
+    /** Change time, note or type of an event.
+     * Nothing is changed if the result would break the invariants checked by validate().
+     * A note of Some(None) removes the note.
+     */
+    pub fn edit_event(
+        &mut self,
+        index: usize,
+        timestamp: Option<u64>,
+        note: Option<Option<String>>,
+        type_of_event: Option<EventType>,
+    ) -> Result<(), TrkError> {
+        self.check_event_index(index)?;
+        let mut edited = self.clone();
+        let event = &mut edited.events[index];
+        if let Some(type_of_event) = type_of_event {
+            if matches!(event.ev_ty, EventType::Commit { .. })
+                || matches!(type_of_event, EventType::Commit { .. })
+            {
+                return Err(TrkError::InvalidArgument(String::from(
+                    "Commits can't change their type.",
+                )));
+            }
+            event.ev_ty = type_of_event;
+        }
+        if let Some(note) = note {
+            event.note = note;
+        }
+        if let Some(timestamp) = timestamp {
+            event.timestamp = timestamp;
+        }
+        if edited.is_running() {
+            edited.update_end();
+        }
+        edited.validate()?;
+        *self = edited;
+        Ok(())
+    }
+
+    /** Delete an event. Deleting a pause or a resume deletes the whole pause,
+     * as one without the other would leave the session inconsistent.
+     */
+    pub fn delete_event(&mut self, index: usize) -> Result<(), TrkError> {
+        self.check_event_index(index)?;
+        let mut edited = self.clone();
+        let partner = match edited.events[index].ev_ty {
+            EventType::Pause => edited.events[index + 1..]
+                .iter()
+                .position(|ev| ev.ev_ty == EventType::Resume)
+                .map(|offset| index + 1 + offset),
+            EventType::Resume => edited.events[..index]
+                .iter()
+                .rposition(|ev| ev.ev_ty == EventType::Pause),
+            _ => None,
+        };
+        if let Some(partner) = partner {
+            edited.events.remove(index.max(partner));
+        }
+        edited.events.remove(index.min(partner.unwrap_or(index)));
+        edited.validate()?;
+        *self = edited;
+        Ok(())
+    }
+
+    /* Events are addressed by the minute, which is what ts_to_date shows */
+    pub fn events_at(&self, timestamp: u64) -> Vec<usize> {
+        const MINUTE: u64 = 60_000;
+        self.events
+            .iter()
+            .enumerate()
+            .filter(|(_, event)| event.timestamp / MINUTE == timestamp / MINUTE)
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    /* One line overview as shown by trk list */
+    pub fn summary(&self, shown_in: Option<Tz>) -> String {
+        let tz = self.timezone(shown_in);
+        let end = if self.is_running() {
+            String::from("running")
+        } else {
+            ts_to_date(self.end, tz)
+        };
+        format!(
+            "{} - {}, {} events",
+            ts_to_date(self.start, tz),
+            end,
+            self.events.len()
+        )
+    }
+
+    /* The events with their indices as shown by trk list <session> */
+    pub fn list_events(&self, shown_in: Option<Tz>) -> String {
+        let tz = self.timezone(shown_in);
+        let mut list = String::new();
+        for (index, event) in self.events.iter().enumerate() {
+            let ty = event.ev_ty.name();
+            let line = format!("{index:>4}  {}  {ty:<6}  ", ts_to_date(event.timestamp, tz));
+            /* Pause notes are joined with <br>, each part goes on a line of its own */
+            let note = match event.commit_message() {
+                Some((subject, _)) => subject.to_string(),
+                None => event
+                    .note
+                    .as_deref()
+                    .unwrap_or_default()
+                    .replace("<br>", "\n"),
+            };
+            let indent = " ".repeat(line.chars().count());
+            let note = note
+                .trim()
+                .lines()
+                .collect::<Vec<&str>>()
+                .join(&format!("\n{indent}"));
+            writeln!(&mut list, "{line}{note}").unwrap();
+        }
+        list
+    }
+
+    pub fn status(&self, now: u64) -> String {
         let mut status = format!(
             "Session running for {}.\n",
-            sec_to_hms_string(self.pause_time() + self.work_time())
+            ms_to_hms_string(self.pause_time(now) + self.work_time(now))
         );
         if self.is_paused() {
             status.push_str(&format!(
                 "    Paused since {}.\n",
-                sec_to_hms_string(get_seconds() - self.events[self.events.len() - 1].timestamp)
+                ms_to_hms_string(now.saturating_sub(self.events[self.events.len() - 1].timestamp))
             ));
         } else {
             match self.events.len() {
                 0 => status.push_str(&String::from("    No events in this session yet!\n")),
                 n => status.push_str(&format!(
-                    "    Last event: {:?}, {} ago.\n",
-                    &self.events[n - 1].ev_ty,
-                    sec_to_hms_string(get_seconds() - self.events[n - 1].timestamp)
+                    "    Last event: {}, {} ago.\n",
+                    self.events[n - 1].ev_ty.name(),
+                    ms_to_hms_string(now.saturating_sub(self.events[n - 1].timestamp))
                 )),
             }
         }
@@ -250,133 +801,565 @@ impl Session {
             n => self
                 .branches
                 .iter()
-                .fold(format!("Worked on {n} branches: "), |res, s| {
-                    res + s + " "
-                }),
+                .fold(format!("Worked on {n} branches: "), |res, s| res + s + " "),
         };
         status.push_str(&branch_str);
+        let commits = self.commit_stats();
+        if commits.commits > 0 {
+            writeln!(&mut status, "    {commits}").unwrap();
+        }
+        status.push_str(&self.labels());
         status.push_str(&format!(
             "    Total work time:  {}\n    \
                                       Total pause time: {}\n",
-            sec_to_hms_string(self.work_time()),
-            sec_to_hms_string(self.pause_time())
+            ms_to_hms_string(self.work_time(now)),
+            ms_to_hms_string(self.pause_time(now))
         ));
         status
     }
 }
 
+/* Length of the time two intervals [start, end) have in common */
+fn overlap(a: (u64, u64), b: (u64, u64)) -> u64 {
+    a.1.min(b.1).saturating_sub(a.0.max(b.0))
+}
+
+impl EventType {
+    /* Name of the event in the html templates and css classes */
+    const fn kind(&self) -> &'static str {
+        match self {
+            Self::Pause => "pause",
+            Self::Resume => "resume",
+            Self::Note => "note",
+            Self::Commit { .. } => "commit",
+        }
+    }
+}
+
+/* Events are shown in the timezone of their session, which passes it on */
 impl HasHTML for Event {
-    fn to_html(&self) -> String {
+    fn html_context(&self, config: &Config, _now: u64, tz: Option<Tz>) -> Value {
+        let tz = tz.unwrap_or_else(system_timezone);
         match self.ev_ty {
-            EventType::Pause => match self.note {
-                Some(ref info) => {
-                    format!(
-                        r#"<div class="entry pause">{}: Started a pause
-    <p class="mininote wordWrap">{}</p>
-</div>"#,
-                        ts_to_date(self.timestamp),
-                        info.clone()
-                    )
-                }
-                None => {
-                    format!(
-                        r#"<div class="entry pause">{}: Started a pause
-</div>"#,
-                        ts_to_date(self.timestamp)
-                    )
+            EventType::Commit {
+                ref hash,
+                ref details,
+            } => {
+                /* The subject is the link text, the body goes below */
+                let (subject, body) = self.commit_message().unwrap_or_default();
+                context! {
+                    kind => self.ev_ty.kind(),
+                    time => ts_to_date(self.timestamp, tz),
+                    hash,
+                    short_hash => hash.get(..SHORT_HASH).unwrap_or(hash),
+                    url => config.commit_url(hash),
+                    subject,
+                    body => Some(body).filter(|body| !body.is_empty()),
+                    details,
+                    tags => self.tags,
                 }
+            }
+            _ => context! {
+                kind => self.ev_ty.kind(),
+                time => ts_to_date(self.timestamp, tz),
+                /* Pause notes are joined with <br>, the template puts the breaks back in */
+                note => self.note.as_deref().map(|note| note.split("<br>").collect::<Vec<&str>>()),
+                tags => self.tags,
             },
-            EventType::Resume => {
+        }
+    }
+}
+
+impl HasHTML for Session {
+    fn html_context(&self, config: &Config, now: u64, tz: Option<Tz>) -> Value {
+        let tz = self.timezone(tz);
+        context! {
+            start => ts_to_date(self.start, tz),
+            end => ts_to_date(self.end, tz),
+            timezone => tz.name(),
+            running => self.running,
+            project => self.project,
+            tags => self.tags,
+            events => self
+                .events
+                .iter()
+                .map(|event| event.html_context(config, now, Some(tz)))
+                .collect::<Vec<Value>>(),
+            branches => self.branches,
+            work_time => ms_to_hms_string(self.work_time(now)),
+            pause_time => ms_to_hms_string(self.pause_time(now)),
+            commits => self.commit_stats().to_string(),
+            commit_count => self.commit_stats().commits,
+        }
+    }
+}
+
+/* Pause notes are joined with <br> for the html report */
+fn tex_note(note: &str) -> String {
+    tex_escape(note.trim().replace("<br>", "\n").as_str())
+}
+
+/** Commits render as list items for the commit list of a session,
+ * all other events as rows of the session table.
+ */
+impl HasTEX for Event {
+    fn to_tex(&self, _now: u64, tz: Option<Tz>) -> String {
+        let date = ts_to_date(self.timestamp, tz.unwrap_or_else(system_timezone));
+        let mut note = match self.commit_message() {
+            Some((subject, _)) => tex_escape(subject),
+            None => self.note.as_deref().map(tex_note).unwrap_or_default(),
+        };
+        for tag in &self.tags {
+            write!(&mut note, " [{}]", tex_escape(tag)).unwrap();
+        }
+        match self.ev_ty {
+            EventType::Pause => format!("{date} & Pause & {note} \\\\\n"),
+            EventType::Resume => format!("{date} & Resume & \\\\\n"),
+            EventType::Note => format!("{date} & Note & {note} \\\\\n"),
+            EventType::Commit { ref hash, .. } => {
+                let short = hash.get(..SHORT_HASH).unwrap_or(hash);
                 format!(
-                    r#"<div class="entry resume">{}: Resumed work
-<hr>
-</div>"#,
-                    ts_to_date(self.timestamp)
+                    "  \\item {date}: \\texttt{{{}}} {note}\n",
+                    tex_escape(short)
                 )
             }
-            /* An EventType::Note note is a Some because it's
-             * 'constructor' function takes a String
-             * (and not Option<String>)
-             */
-            EventType::Note => match self.note {
-                Some(ref text) => {
-                    format!(
-                        r#"<div class="entry note wordWrap">{}: Note: {
-}
-<hr>
-</div>"#,
-                        ts_to_date(self.timestamp),
-                        text
-                    )
-                }
-                None => unreachable!(),
-            },
-            /* It is safe to unwrap an EventType::Commit note because if
-             * a commit has no message something went really wrong
-             * (like parsing the output of `git log` in git_commit_message()
-             */
-            EventType::Commit { ref hash } => match self.note {
-                Some(ref text) => {
-                    format!(
-                        r#"<div class="entry commit git_info wordWrap">{}: Commit id: {}
-    <p class="mininote wordWrap">message: {}</p>
-  <hr>
-</div>"#,
-                        ts_to_date(self.timestamp),
-                        hash,
-                        text
-                    )
-                }
-                None => unreachable!(),
-            },
         }
     }
 }
 
-impl HasHTML for Session {
-    fn to_html(&self) -> String {
-        let mut html = format!(
-            r#"<section class="session">
-    <h1 class="sessionheader">Session on {}</h1>"#,
-            ts_to_date(self.start)
+impl HasTEX for Session {
+    fn to_tex(&self, now: u64, tz: Option<Tz>) -> String {
+        let tz = self.timezone(tz);
+        let mut tex = format!(
+            "\\section*{{Session on {} ({})}}\n\n",
+            ts_to_date(self.start, tz),
+            tex_escape(tz.name())
         );
-
-        for event in &self.events {
-            html.push_str(&event.to_html());
+        if let Some(ref project) = self.project {
+            writeln!(&mut tex, "Project: {}\n", tex_escape(project)).unwrap();
+        }
+        if !self.tags.is_empty() {
+            let tags = self
+                .tags
+                .iter()
+                .map(|tag| tex_escape(tag))
+                .collect::<Vec<String>>();
+            writeln!(&mut tex, "Tags: {}\n", tags.join(", ")).unwrap();
         }
 
-        write!(
-            &mut html,
-            r#"<h2 class="sessionfooter">Ended on {}</h2>"#,
-            ts_to_date(self.end)
-        )
-        .unwrap();
+        let (commits, events): (Vec<&Event>, Vec<&Event>) = self
+            .events
+            .iter()
+            .partition(|event| matches!(event.ev_ty, EventType::Commit { .. }));
+
+        if !events.is_empty() {
+            tex.push_str(
+                r"\begin{longtable}{@{}l l p{0.55\textwidth}@{}}
+\toprule
+Time & Event & Note \\
+\midrule
+",
+            );
+            for event in events {
+                tex.push_str(&event.to_tex(now, Some(tz)));
+            }
+            tex.push_str("\\bottomrule\n\\end{longtable}\n\n");
+        }
 
-        let mut branch_str = String::new();
-        match self.branches.len() {
-            0 => {}
-            n => {
-                write!(&mut branch_str, "Worked on {n} branches: ").unwrap();
-                for branch in &self.branches {
-                    write!(&mut branch_str, "{branch} ").unwrap();
+        /* Like git_info in the html report, hidden unless \showcommitstrue */
+        if !commits.is_empty() || !self.branches.is_empty() {
+            tex.push_str("\\ifshowcommits\n");
+            if !self.branches.is_empty() {
+                let branches = self
+                    .branches
+                    .iter()
+                    .map(|branch| format!("\\texttt{{{}}}", tex_escape(branch)))
+                    .collect::<Vec<String>>()
+                    .join(", ");
+                writeln!(
+                    &mut tex,
+                    "Worked on {} branches: {branches}\n",
+                    self.branches.len()
+                )
+                .unwrap();
+            }
+            if !commits.is_empty() {
+                write!(
+                    &mut tex,
+                    "\\paragraph{{Commits}} {}\n\\begin{{itemize}}\n",
+                    self.commit_stats()
+                )
+                .unwrap();
+                for commit in commits {
+                    tex.push_str(&commit.to_tex(now, Some(tz)));
                 }
+                tex.push_str("\\end{itemize}\n");
             }
-        };
+            tex.push_str("\\fi\n\n");
+        }
 
         write!(
-            &mut html,
-            r#"<section class="summary">
-    <p class="git_info">{}</p>
-    <p>Worked for {}</p>
-    <p>Paused for {}</p>
-</div></section>"#,
-            branch_str,
-            sec_to_hms_string(self.work_time()),
-            sec_to_hms_string(self.pause_time())
+            &mut tex,
+            r"\begin{{tabular}}{{@{{}}ll@{{}}}}
+Ended on & {} \\
+Worked for & {} \\
+Paused for & {} \\
+\end{{tabular}}
+",
+            ts_to_date(self.end, tz),
+            ms_to_hms_string(self.work_time(now)),
+            ms_to_hms_string(self.pause_time(now))
         )
         .unwrap();
+        tex
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const START: u64 = 1_700_000_000_000;
+    const SECOND: u64 = 1000;
+    const MINUTE: u64 = 60 * SECOND;
+
+    fn commit(hash: &str) -> EventType {
+        EventType::Commit {
+            hash: hash.to_string(),
+            details: None,
+        }
+    }
+
+    /* Started at START, paused for 5 minutes after 10 minutes */
+    fn session_with_pause() -> Session {
+        let mut session = Session::new(START);
+        session
+            .push_event(START + 10 * MINUTE, None, None, EventType::Pause)
+            .unwrap();
+        session
+            .push_event(START + 15 * MINUTE, None, None, EventType::Resume)
+            .unwrap();
+        session
+    }
+
+    #[test]
+    fn times_between_count_only_the_overlap() {
+        let mut session = session_with_pause();
+        session.finalize(START + 30 * MINUTE).unwrap();
+        let now = START + 60 * MINUTE;
+        let (from, to) = (START + 12 * MINUTE, START + 20 * MINUTE);
+        assert_eq!(session.pause_time_between(from, to, now), 3 * MINUTE);
+        assert_eq!(session.work_time_between(from, to, now), 5 * MINUTE);
+        assert_eq!(session.work_time_between(START - MINUTE, START, now), 0);
+        assert_eq!(session.work_time_between(to, u64::MAX, now), 10 * MINUTE);
+        assert_eq!(
+            session.work_time_between(0, to, now) + session.work_time_between(to, u64::MAX, now),
+            session.work_time(now)
+        );
+    }
+
+    #[test]
+    fn pauses_are_inserted_within_the_session() {
+        let mut session = Session::new(START);
+        session.finalize(START + 60 * MINUTE).unwrap();
+        let at = |minutes: u64| START + minutes * MINUTE;
+        let now = at(90);
+        let refused = |session: &mut Session, from: u64, to: u64, now: u64| {
+            matches!(
+                session.insert_pause(from, to, None, now),
+                Err(TrkError::Inconsistent(_))
+            )
+        };
+        assert!(refused(&mut session, at(20), at(20), now));
+        assert!(refused(&mut session, START - MINUTE, at(20), now));
+        assert!(refused(&mut session, at(50), at(70), now));
+        assert!(session.events().is_empty());
+        session
+            .insert_pause(START + 40 * MINUTE, START + 60 * MINUTE, None, now)
+            .unwrap();
+        assert_eq!(session.pause_time(now), 20 * MINUTE);
+
+        let mut running = Session::new(START);
+        let now = START + 30 * MINUTE;
+        assert!(refused(&mut running, at(20), at(40), now));
+        assert!(running.events().is_empty());
+    }
+
+    #[test]
+    fn pause_recorded_by_a_clock_ahead() {
+        let mut session = Session::new(START);
+        session
+            .insert_pause(
+                START + 20 * MINUTE,
+                START + 40 * MINUTE,
+                None,
+                START + 50 * MINUTE,
+            )
+            .unwrap();
+        /* Seen from a machine whose clock is behind */
+        let now = START + 30 * MINUTE;
+        assert_eq!(session.pause_time(now), 10 * MINUTE);
+        assert_eq!(session.work_time(now), 20 * MINUTE);
+        assert_eq!(
+            session.work_time_between(START + 25 * MINUTE, u64::MAX, now),
+            0
+        );
+        let now = START + 50 * MINUTE;
+        assert_eq!(session.pause_time(now), 20 * MINUTE);
+        assert_eq!(session.work_time(now), 30 * MINUTE);
+    }
+
+    #[test]
+    fn work_and_pause_time_of_running_session() {
+        let session = session_with_pause();
+        assert_eq!(session.pause_time(START + 60 * MINUTE), 5 * MINUTE);
+        assert_eq!(session.work_time(START + 60 * MINUTE), 55 * MINUTE);
+    }
+
+    #[test]
+    fn open_pause_lasts_until_now() {
+        let mut session = session_with_pause();
+        session
+            .push_event(START + 20 * MINUTE, None, None, EventType::Pause)
+            .unwrap();
+        assert!(session.is_paused());
+        assert_eq!(session.pause_time(START + 30 * MINUTE), 15 * MINUTE);
+        assert_eq!(session.work_time(START + 30 * MINUTE), 15 * MINUTE);
+    }
+
+    #[test]
+    fn several_pauses_add_up() {
+        let mut session = session_with_pause();
+        let pause = START + 20 * MINUTE;
+        let resume = pause + 90 * SECOND;
+        session
+            .push_event(pause, Some(pause), None, EventType::Pause)
+            .unwrap();
+        session
+            .push_event(resume, Some(resume), None, EventType::Resume)
+            .unwrap();
+        assert_eq!(session.pause_time(START + 30 * MINUTE), 390 * SECOND);
+        assert_eq!(session.work_time(START + 30 * MINUTE), 1410 * SECOND);
+    }
+
+    #[test]
+    fn pause_and_resume_alternate() {
+        let mut session = session_with_pause();
+        assert!(matches!(
+            session.push_event(START + 20 * MINUTE, None, None, EventType::Resume),
+            Err(TrkError::NotPaused)
+        ));
+        session
+            .push_event(START + 20 * MINUTE, None, None, EventType::Pause)
+            .unwrap();
+        assert!(matches!(
+            session.push_event(START + 21 * MINUTE, None, None, EventType::Pause),
+            Err(TrkError::AlreadyPaused)
+        ));
+    }
+
+    #[test]
+    fn retroactive_events_must_not_precede_the_last_event() {
+        let mut session = session_with_pause();
+        let now = START + 60 * MINUTE;
+        assert!(matches!(
+            session.push_event(now, Some(START + 14 * MINUTE), None, EventType::Note),
+            Err(TrkError::InvalidTimestamp)
+        ));
+        let earlier = START + 30 * MINUTE;
+        session
+            .push_event(
+                now,
+                Some(earlier),
+                Some(String::from("earlier")),
+                EventType::Note,
+            )
+            .unwrap();
+        assert_eq!(session.events().len(), 3);
+        assert_eq!(session.events()[2].timestamp(), earlier);
+        assert_eq!(session.events()[2].note(), Some("earlier"));
+        assert!(session.validate().is_ok());
+    }
+
+    #[test]
+    fn retroactive_event_must_not_precede_the_start() {
+        let mut session = Session::new(START);
+        assert!(matches!(
+            session.push_event(START + MINUTE, Some(START - 1), None, EventType::Pause),
+            Err(TrkError::InvalidTimestamp)
+        ));
+        session
+            .push_event(START + MINUTE, Some(START), None, EventType::Pause)
+            .unwrap();
+    }
+
+    #[test]
+    fn events_at_the_same_time_keep_their_order() {
+        let mut session = Session::new(START);
+        let time = START + 10 * MINUTE;
+        session
+            .push_event(time, None, Some(String::from("first")), EventType::Note)
+            .unwrap();
+        session
+            .push_event(time, None, None, commit("abc1234"))
+            .unwrap();
+        session
+            .push_event(time, Some(time), None, EventType::Pause)
+            .unwrap();
+        session
+            .push_event(time, Some(time), None, EventType::Resume)
+            .unwrap();
+        let kinds: Vec<&str> = session.events().iter().map(|e| e.ev_ty().name()).collect();
+        assert_eq!(kinds, ["Note", "Commit", "Pause", "Resume"]);
+        assert!(session.validate().is_ok());
+        assert_eq!(session.pause_time(time), 0);
+    }
+
+    #[test]
+    fn notes_during_a_pause_go_to_the_pause() {
+        let mut session = Session::new(START);
+        let lunch = Some(String::from("lunch"));
+        session
+            .push_event(START + MINUTE, None, lunch, EventType::Pause)
+            .unwrap();
+        let soup = Some(String::from("soup"));
+        session
+            .push_event(START + 2 * MINUTE, None, soup, EventType::Note)
+            .unwrap();
+        assert_eq!(session.events().len(), 1);
+        assert_eq!(session.events()[0].note(), Some("lunch<br>soup"));
+    }
+
+    #[test]
+    fn commit_during_a_pause_resumes_first() {
+        let mut session = Session::new(START);
+        session
+            .push_event(START + MINUTE, None, None, EventType::Pause)
+            .unwrap();
+        session
+            .push_event(START + 5 * MINUTE, None, None, commit("abc1234"))
+            .unwrap();
+        let kinds: Vec<&str> = session.events().iter().map(|e| e.ev_ty().name()).collect();
+        assert_eq!(kinds, ["Pause", "Resume", "Commit"]);
+        assert_eq!(session.events()[2].timestamp(), START + 5 * MINUTE);
+        assert_eq!(session.pause_time(START + 10 * MINUTE), 4 * MINUTE);
+    }
+
+    #[test]
+    fn finalize_ends_at_the_real_end() {
+        let mut session = session_with_pause();
+        session.finalize(START + 60 * MINUTE).unwrap();
+        assert!(!session.is_running());
+        assert_eq!(session.end, START + 60 * MINUTE);
+        assert_eq!(session.work_time(START + 120 * MINUTE), 55 * MINUTE);
+        assert!(session.validate().is_ok());
+    }
+
+    #[test]
+    fn finalize_ends_an_open_pause() {
+        let mut session = Session::new(START);
+        session
+            .push_event(START + 10 * MINUTE, None, None, EventType::Pause)
+            .unwrap();
+        session.finalize(START + 15 * MINUTE).unwrap();
+        assert!(!session.is_running());
+        assert!(!session.is_paused());
+        assert_eq!(session.events()[1].ev_ty(), &EventType::Resume);
+        assert_eq!(session.events()[1].timestamp(), START + 15 * MINUTE);
+        /* A finalized session doesn't grow anymore */
+        assert_eq!(session.pause_time(START + 120 * MINUTE), 5 * MINUTE);
+        assert_eq!(session.work_time(START + 120 * MINUTE), 10 * MINUTE);
+        assert!(matches!(
+            session.push_event(START + 20 * MINUTE, None, None, EventType::Note),
+            Err(TrkError::NotRunning)
+        ));
+    }
+
+    #[test]
+    fn finalize_must_not_precede_the_last_event() {
+        let mut session = session_with_pause();
+        assert!(matches!(
+            session.finalize(START + 15 * MINUTE - 1),
+            Err(TrkError::InvalidTimestamp)
+        ));
+        assert!(session.is_running());
+        session.finalize(START + 15 * MINUTE).unwrap();
+        assert_eq!(session.work_time(START + 15 * MINUTE), 10 * MINUTE);
+    }
+
+    #[test]
+    fn sessions_which_touch_do_not_overlap() {
+        let mut first = Session::new(START);
+        first.finalize(START + 60 * MINUTE).unwrap();
+        let mut second = Session::new(START + 60 * MINUTE);
+        assert!(!first.overlaps(&second));
+        assert!(first.contains(START + 60 * MINUTE));
+        second.finalize(START + 90 * MINUTE).unwrap();
+        let mut third = Session::new(START + 59 * MINUTE);
+        third.finalize(START + 61 * MINUTE).unwrap();
+        assert!(first.overlaps(&third));
+        assert!(second.overlaps(&third));
+    }
+
+    #[test]
+    fn short_hashes_are_the_same_in_both_reports() {
+        let mut session = Session::new(START);
+        for hash in ["0123456789abcdef", "abcdefgé", "abcdefé", "abc"] {
+            session.push_event(START, None, None, commit(hash)).unwrap();
+        }
+        let tex = session.to_tex(START, Some(Tz::UTC));
+        let html = session.html_context(&Config::new(), START, Some(Tz::UTC));
+        let events = html.get_attr("events").unwrap();
+        for (index, short) in ["0123456", "abcdefg", "abcdefé", "abc"]
+            .into_iter()
+            .enumerate()
+        {
+            assert!(tex.contains(&format!("\\texttt{{{short}}}")), "{tex}");
+            let event = events.get_item(&Value::from(index)).unwrap();
+            assert_eq!(event.get_attr("short_hash").unwrap().to_string(), short);
+        }
+    }
+
+    #[test]
+    fn html_context_shows_durations_up_to_now() {
+        let mut session = session_with_pause();
+        let note = Some(String::from("<b>bold</b>"));
+        session
+            .push_event(START + 20 * MINUTE, None, note, EventType::Note)
+            .unwrap();
+        let context = session.html_context(&Config::new(), START + 60 * MINUTE, None);
+        assert_eq!(
+            context.get_attr("work_time").unwrap().to_string(),
+            "55 minutes"
+        );
+        assert_eq!(
+            context.get_attr("pause_time").unwrap().to_string(),
+            "5 minutes"
+        );
+        assert!(context.get_attr("running").unwrap().is_true());
+    }
+
+    #[test]
+    fn shown_in_the_recorded_timezone() {
+        let mut session = Session::new(START);
+        session.set_timezone(Tz::Asia__Tokyo);
+        assert_eq!(
+            session.summary(None),
+            "2023-11-15, 07:13 - running, 0 events"
+        );
+        assert_eq!(
+            session.summary(Some(Tz::UTC)),
+            "2023-11-14, 22:13 - running, 0 events"
+        );
+        let context = session.html_context(&Config::new(), START, None);
+        assert_eq!(
+            context.get_attr("timezone").unwrap().to_string(),
+            "Asia/Tokyo"
+        );
 
-        write!(&mut html, "</section>").unwrap();
-        html
+        let json = serde_json::to_string(&session).unwrap();
+        assert!(json.contains("\"timezone\":\"Asia/Tokyo\""));
+        let parsed: Session = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.timezone(None), Tz::Asia__Tokyo);
     }
 }
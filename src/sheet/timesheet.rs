@@ -1,159 +1,248 @@
-use std::fs::{self, OpenOptions};
-use std::io::prelude::*;
-use std::path::Path;
-use std::{env, process};
-/* Alias to avoid naming conflict for write_all!() */
-use std::fmt::Write as std_write;
+use std::fmt::Write;
+use std::sync::Arc;
 
+use chrono_tz::Tz;
+use minijinja::{context, Value};
 use serde::{Deserialize, Serialize};
-use url::Url;
-use url_open::UrlOpen;
 
-use serde_json::{from_str, to_string};
+use serde_json::{from_str, to_string_pretty};
 
+use crate::clock::{Clock, SystemClock};
 use crate::config::Config;
-use crate::sheet::traits::HasHTML;
+use crate::error::TrkError;
+use crate::sheet::traits::{HasHTML, HasTEX};
 
+use crate::sheet::breakdown::Breakdown;
+use crate::sheet::filter::Filter;
+use crate::sheet::schema::{migrate, SCHEMA_VERSION};
 use crate::sheet::session::EventType;
-use crate::sheet::session::Session;
-use crate::util::{
-    format_file, get_seconds, git_author, git_commit_message, git_init_trk, sec_to_hms_string,
-};
+use crate::sheet::session::{CommitDetails, CommitStats, Session};
+use crate::util::{ms_to_hms_string, system_timezone, tex_escape, ts_to_date};
+
+/* What trk backfill did with the commits it found */
+#[derive(Default, Debug)]
+pub struct Backfill {
+    pub inserted: usize,
+    /// Already in the sheet
+    pub recorded: usize,
+    /// Not made during a session
+    pub outside: usize,
+    /// Made during a pause
+    pub refused: usize,
+}
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Timesheet {
+    /// Format of timesheet.json, see schema.rs
+    schema_version: u64,
     start: u64,
     end: u64,
     config: Config,
     sessions: Vec<Session>,
+    /// Where now comes from, the system clock unless set otherwise
+    #[serde(skip, default = "system_clock")]
+    clock: Arc<dyn Clock>,
+    /// Timezone of this run only, like --tz of trk report. Takes precedence over the config
+    #[serde(skip)]
+    shown_in: Option<Tz>,
+    /// Sessions the LaTeX report of this run shows, like --project of trk report
+    #[serde(skip)]
+    selected: Filter,
+}
+
+fn system_clock() -> Arc<dyn Clock> {
+    Arc::new(SystemClock)
 }
 
 impl Timesheet {
-    /** Initializes the .trk/timesheet.json file which holds
-     * the serialized timesheet
-     * Returns Some(newTimesheet) if operation succeeded */
-    pub fn init(author_name: Option<String>) -> Option<Self> {
-        /* Check if file already exists (no init permitted) */
-        if Self::is_init() {
-            println!("Timesheet is already initialized!");
-            return None;
-        }
-        /* File does not exist, initialize */
-        let git_author_name = git_author();
-        let author_name = match author_name {
-            Some(name) => name,
-            None => {
-                if let Some(git_name) = git_author_name {
-                    git_name
-                } else {
-                    println!("Empty name not permitted.\n\tPlease run with 'trk init <name>'");
-                    process::exit(0);
-                }
-            }
-        };
+    /** An empty sheet for the given user, started now */
+    pub fn new(user_name: String) -> Self {
+        Self::with_clock(user_name, system_clock())
+    }
+
+    /** An empty sheet for the given user, started at the time of the clock */
+    pub fn with_clock(user_name: String, clock: Arc<dyn Clock>) -> Self {
         let mut config = Config::new();
-        config.user_name = Some(author_name);
-        let now = get_seconds();
-        let sheet = Self {
+        config.user_name = Some(user_name);
+        let now = clock.now();
+        Self {
+            schema_version: SCHEMA_VERSION,
             start: now,
-            end: now + 1,
+            end: now,
             config,
             sessions: Vec::<Session>::new(),
-        };
-        if sheet.write_files() {
-            git_init_trk();
-            Some(sheet)
-        } else {
-            None
+            clock,
+            shown_in: None,
+            selected: Filter::default(),
         }
     }
 
-    fn is_init() -> bool {
-        Path::new("./.trk/timesheet.json").exists() && Self::load_from_file().is_some()
+    /** Take the time from another clock, like a FakeClock in tests */
+    pub fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.clock = clock;
     }
 
-    pub fn new_session(&mut self, timestamp: Option<u64>) -> bool {
-        let possible = self.sessions.last_mut().map_or(true, |session| {
-            if session.is_running() {
-                println!("Last session is still running.");
-            }
-            !session.is_running()
-        });
-        if possible {
-            match timestamp {
-                Some(timestamp) => {
-                    let is_valid_ts = match self.sessions.last() {
-                        None => timestamp > self.start,
-                        Some(last_session) => timestamp > last_session.end,
-                    };
-                    if is_valid_ts {
-                        self.sessions.push(Session::new(Some(timestamp)));
-                    } else {
-                        println!("That timestamp is invalid.");
-                        process::exit(0);
-                    }
-                }
-                None => {
-                    self.sessions.push(Session::new(None));
-                }
-            };
-        }
-        possible
+    pub fn now(&self) -> u64 {
+        self.clock.now()
     }
 
-    pub fn end_session(&mut self, timestamp: Option<u64>) {
-        match self.sessions.last_mut() {
-            Some(session) => {
-                session.update_end();
-                session.finalize(timestamp);
-                self.end = session.end + 1;
-            }
-            None => println!("No session to finalize."),
-        }
+    /** Show all times in the given timezone, whatever the config and the sessions say */
+    pub fn show_in(&mut self, tz: Tz) {
+        self.shown_in = Some(tz);
     }
 
-    pub fn pause(&mut self, timestamp: Option<u64>, note: Option<String>) {
-        match self.sessions.last_mut() {
-            Some(session) => {
-                session.push_event(timestamp, note, EventType::Pause);
+    /** Only show the sessions selected by the filter in the LaTeX report, see HasTEX */
+    pub fn select(&mut self, filter: Filter) {
+        self.selected = filter;
+    }
+
+    /** The timezone everything is shown in, None to show each session in its own */
+    pub fn shown_in(&self) -> Option<Tz> {
+        self.shown_in.or(self.config.timezone)
+    }
+
+    /** The timezone new sessions are recorded in and times given as arguments are read in */
+    pub fn timezone(&self) -> Tz {
+        self.shown_in().unwrap_or_else(system_timezone)
+    }
+
+    pub fn new_session(&mut self, timestamp: Option<u64>) -> Result<(), TrkError> {
+        if self.sessions.last().is_some_and(Session::is_running) {
+            return Err(TrkError::SessionRunning);
+        }
+        match timestamp {
+            Some(timestamp) => {
+                /* A session may begin when the one before ended */
+                let is_valid_ts = match self.sessions.last() {
+                    None => timestamp >= self.start,
+                    Some(last_session) => timestamp >= last_session.end,
+                };
+                if !is_valid_ts {
+                    return Err(TrkError::InvalidTimestamp);
+                }
+                self.sessions.push(Session::new(timestamp));
+            }
+            None => {
+                self.sessions.push(Session::new(self.clock.now()));
             }
-            None => println!("No session to pause."),
+        };
+        let timezone = self.timezone();
+        if let Some(session) = self.sessions.last_mut() {
+            session.set_timezone(timezone);
         }
+        Ok(())
     }
 
-    pub fn resume(&mut self, timestamp: Option<u64>) {
-        match self.sessions.last_mut() {
-            Some(session) => {
-                session.push_event(timestamp, None, EventType::Resume);
-            }
-            None => println!("No session to pause."),
+    pub fn end_session(&mut self, timestamp: Option<u64>) -> Result<(), TrkError> {
+        let session = self.sessions.last_mut().ok_or(TrkError::NoSession)?;
+        if !session.is_running() {
+            return Err(TrkError::NotRunning);
         }
+        session.update_end();
+        session.finalize(timestamp.unwrap_or_else(|| self.clock.now()))?;
+        self.end = session.end;
+        Ok(())
     }
 
-    pub fn note(&mut self, timestamp: Option<u64>, note_text: String) {
-        match self.sessions.last_mut() {
-            Some(session) => {
-                session.push_event(timestamp, Some(note_text), EventType::Note);
-            }
-            None => println!("No session to add note to."),
+    pub fn pause(&mut self, timestamp: Option<u64>, note: Option<String>) -> Result<(), TrkError> {
+        let now = self.clock.now();
+        let session = self.sessions.last_mut().ok_or(TrkError::NoSession)?;
+        session.push_event(now, timestamp, note, EventType::Pause)
+    }
+
+    pub fn resume(&mut self, timestamp: Option<u64>) -> Result<(), TrkError> {
+        let now = self.clock.now();
+        let session = self.sessions.last_mut().ok_or(TrkError::NoSession)?;
+        session.push_event(now, timestamp, None, EventType::Resume)
+    }
+
+    pub fn note(
+        &mut self,
+        timestamp: Option<u64>,
+        note_text: String,
+        tags: Vec<String>,
+    ) -> Result<(), TrkError> {
+        let now = self.clock.now();
+        let session = self.sessions.last_mut().ok_or(TrkError::NoSession)?;
+        session.push_event(now, timestamp, Some(note_text), EventType::Note)?;
+        session.tag_last_event(tags);
+        Ok(())
+    }
+
+    /** Set the project and add tags to a session, the last one if None */
+    pub fn tag_session(
+        &mut self,
+        session: Option<usize>,
+        project: Option<String>,
+        tags: Vec<String>,
+    ) -> Result<(), TrkError> {
+        let index = match session {
+            Some(index) => self.session_index(index)?,
+            None => self
+                .sessions
+                .len()
+                .checked_sub(1)
+                .ok_or(TrkError::NoSession)?,
+        };
+        let session = &mut self.sessions[index];
+        if let Some(project) = project {
+            session.set_project(project);
         }
+        session.add_tags(tags);
+        Ok(())
     }
 
-    pub fn add_commit(&mut self, hash: String) {
+    pub fn add_commit(
+        &mut self,
+        hash: String,
+        details: Option<CommitDetails>,
+    ) -> Result<(), TrkError> {
         let new_needed = self
             .sessions
             .last()
-            .map_or(true, |session| !session.is_running());
+            .is_none_or(|session| !session.is_running());
         if new_needed {
-            self.new_session(None);
+            self.new_session(None)?;
         }
-        match self.sessions.last_mut() {
-            Some(session) => {
-                let message = git_commit_message(&hash).unwrap_or_default();
-                session.push_event(None, Some(message), EventType::Commit { hash });
+        let now = self.clock.now();
+        let session = self.sessions.last_mut().ok_or(TrkError::NoSession)?;
+        session.push_event(now, None, None, EventType::Commit { hash, details })
+    }
+
+    /** Insert commits the post-commit hook missed into the sessions they were made in,
+     * at their commit time. Commits outside of sessions and recorded ones are left out.
+     * details is only asked for the commits which are inserted.
+     */
+    pub fn backfill(
+        &mut self,
+        commits: Vec<(String, u64)>,
+        details: impl Fn(&str) -> Option<CommitDetails>,
+    ) -> Backfill {
+        let mut backfill = Backfill::default();
+        for (hash, timestamp) in commits {
+            if self
+                .sessions
+                .iter()
+                .any(|session| session.has_commit(&hash))
+            {
+                backfill.recorded += 1;
+                continue;
+            }
+            let Some(session) = self
+                .sessions
+                .iter_mut()
+                .find(|session| session.contains(timestamp))
+            else {
+                backfill.outside += 1;
+                continue;
+            };
+            let details = details(&hash);
+            match session.insert_commit(timestamp, hash, details) {
+                Ok(()) => backfill.inserted += 1,
+                Err(_) => backfill.refused += 1,
             }
-            None => println!("No session to add commit to."),
         }
+        backfill
     }
 
     pub fn add_branch(&mut self, name: String) {
@@ -162,316 +251,1039 @@ impl Timesheet {
         }
     }
 
-    fn write_to_html(&self, ago: Option<u64>) -> bool {
-        // TODO: avoid time-of-check-to-time-of-use race risk
-        let path = Path::new("./timesheet.html");
-        let file = OpenOptions::new()
-            .write(true)
-            .truncate(true)
-            .create(true)
-            .open(path);
-
-        match file {
-            Ok(mut file) => {
-                file.write_all(self.to_html(ago).as_bytes()).unwrap();
-                format_file("timesheet.html");
-                /* Save was successful */
-                true
-            }
-            Err(e) => {
-                println!("Could not report sheet! {e}");
-                false
-            }
+    fn session_index(&self, index: usize) -> Result<usize, TrkError> {
+        if index < self.sessions.len() {
+            Ok(index)
+        } else {
+            Err(TrkError::InvalidArgument(format!(
+                "No session {index}, there are {} sessions.",
+                self.sessions.len()
+            )))
         }
     }
 
-    fn write_last_session_html(&self) -> bool {
-        let session = match self.sessions.last() {
-            Some(session) => session,
-            None => return true,
-        };
-        let path = Path::new("./session.html");
-        let file = OpenOptions::new()
-            .write(true)
-            .truncate(true)
-            .create(true)
-            .open(path);
-
-        let mut file = match file {
-            Ok(file) => file,
-            Err(e) => {
-                eprintln!("Could not write report to session.html! {e}");
-                return false;
+    /* Say which session an inconsistency was found in */
+    fn in_session(index: usize, e: TrkError) -> TrkError {
+        match e {
+            TrkError::Inconsistent(problem) => {
+                TrkError::Inconsistent(format!("session {index}: {problem}"))
             }
-        };
+            e => e,
+        }
+    }
 
-        let stylesheets = if self.config.show_commits {
-            r#"<link rel="stylesheet" type="text/css" href=".trk/style.css">
-"#
-        } else {
-            r#"<link rel="stylesheet" type="text/css" href=".trk/style.css">
-<link rel="stylesheet" type="text/css" href=".trk/no_git_info.css">
-"#
-        };
+    /** Find the session and event index of the event at the given minute */
+    pub fn find_event(&self, timestamp: u64) -> Result<(usize, usize), TrkError> {
+        let mut found = self.sessions.iter().enumerate().flat_map(|(s, session)| {
+            session
+                .events_at(timestamp)
+                .into_iter()
+                .map(move |event| (s, event))
+        });
+        match (found.next(), found.next()) {
+            (Some(position), None) => Ok(position),
+            (None, _) => Err(TrkError::InvalidArgument(format!(
+                "No event at {}.",
+                ts_to_date(timestamp, self.timezone())
+            ))),
+            (Some(..), Some(..)) => Err(TrkError::InvalidArgument(format!(
+                "More than one event at {}, use indices instead.",
+                ts_to_date(timestamp, self.timezone())
+            ))),
+        }
+    }
 
-        let html = format!(
-            r#"<!DOCTYPE html>
-<html>
-<head>
-  {}
-  <title>{} for {}</title>
-</head>
-<body>
-{}
-</body>
-</html>"#,
-            stylesheets,
-            "Session",
-            "Rafael Bachmann",
-            session.to_html()
-        );
-        file.write_all(html.as_bytes()).unwrap();
-        format_file("session.html");
-        /* Save was successful */
-        true
+    pub fn edit_event(
+        &mut self,
+        session: usize,
+        event: usize,
+        timestamp: Option<u64>,
+        note: Option<Option<String>>,
+        type_of_event: Option<EventType>,
+    ) -> Result<(), TrkError> {
+        let session = self.session_index(session)?;
+        self.sessions[session]
+            .edit_event(event, timestamp, note, type_of_event)
+            .map_err(|e| Self::in_session(session, e))
     }
 
-    fn write_to_json(&self) -> bool {
-        if !Path::new("./.trk").exists() {
-            if let Err(e) = fs::create_dir("./.trk") {
-                println!("Could not create .trk directory. Error: {e}");
-                process::exit(0);
-            }
+    pub fn delete_event(&mut self, session: usize, event: usize) -> Result<(), TrkError> {
+        let session = self.session_index(session)?;
+        self.sessions[session]
+            .delete_event(event)
+            .map_err(|e| Self::in_session(session, e))
+    }
+
+    pub fn sessions(&self) -> &[Session] {
+        &self.sessions
+    }
+
+    /** Add finalized sessions from elsewhere, keeping the sessions in chronological order.
+     * The caller makes sure they don't overlap with the existing sessions.
+     */
+    pub fn merge_sessions(&mut self, sessions: Vec<Session>) {
+        for session in sessions {
+            /* Sessions must not start before the sheet */
+            self.start = self.start.min(session.start);
+            self.end = self.end.max(session.end);
+            let index = self.sessions.partition_point(|s| s.start < session.start);
+            self.sessions.insert(index, session);
         }
+    }
 
-        let path = Path::new("./.trk/timesheet.json");
-        let file = OpenOptions::new()
-            .write(true)
-            .truncate(true)
-            .create(true)
-            .open(path);
-
-        match file {
-            Ok(mut file) => {
-                /* Convert the sheet to a JSON string. */
-                let serialized = to_string(&self).expect("Could not write serialized time sheet.");
-                file.write_all(serialized.as_bytes()).unwrap();
-                /* Save was successful */
-                true
+    /** Combine this sheet with another version of it, like the one of another machine.
+     * Sessions with the same start are merged, the others added. The config is this sheet's.
+     * Nothing is merged if sessions overlap or can't be merged, all problems are listed.
+     */
+    pub fn merge(mut self, other: Self) -> Result<Self, TrkError> {
+        let mut report = String::new();
+        let mut added = Vec::new();
+        for session in other.sessions {
+            match self.sessions.iter().position(|s| s.start == session.start) {
+                Some(index) => match self.sessions[index].merge(&session) {
+                    Ok(merged) => self.sessions[index] = merged,
+                    Err(e) => writeln!(report, "    session {index}: {e}").unwrap(),
+                },
+                None => added.push(session),
             }
-            Err(e) => {
-                eprintln!("Could not open timesheet.json file: {e}");
-                false
+        }
+        for session in &added {
+            for (index, known) in self.sessions.iter().enumerate() {
+                if session.overlaps(known) {
+                    writeln!(
+                        report,
+                        "    {} overlaps session {index} ({})",
+                        session.summary(self.shown_in()),
+                        known.summary(self.shown_in())
+                    )
+                    .unwrap();
+                }
             }
         }
+        if !report.is_empty() {
+            return Err(TrkError::InvalidArgument(format!(
+                "Nothing merged:\n{}",
+                report.trim_end()
+            )));
+        }
+        self.start = self.start.min(other.start);
+        self.end = self.end.max(other.end);
+        self.merge_sessions(added);
+        Ok(self)
     }
 
-    fn write_stylesheets(filename: &str, content: &'static str) -> bool {
-        // TODO: avoid time-of-check-to-time-of-use race risk
-        let mut file_path = env::current_dir().unwrap();
-        file_path.push(filename);
-        if file_path.exists() {
-            true
-        } else {
-            let file = OpenOptions::new()
-                .write(true)
-                .truncate(true)
-                .create(true)
-                .open(&file_path);
-            match file {
-                Ok(mut file) => {
-                    file.write_all(content.as_bytes()).unwrap();
-                    /* Save was successful */
-                    true
-                }
-                Err(e) => {
-                    println!("Could not report sheet! {e}");
-                    false
-                }
-            }
+    pub fn session(&self, index: usize) -> Result<&Session, TrkError> {
+        Ok(&self.sessions[self.session_index(index)?])
+    }
+
+    pub fn insert_pause(
+        &mut self,
+        session: usize,
+        from: u64,
+        to: u64,
+        note: Option<String>,
+    ) -> Result<(), TrkError> {
+        let session = self.session_index(session)?;
+        self.sessions[session]
+            .insert_pause(from, to, note, self.clock.now())
+            .map_err(|e| Self::in_session(session, e))
+    }
+
+    pub fn delete_session(&mut self, session: usize) -> Result<(), TrkError> {
+        let session = self.session_index(session)?;
+        self.sessions.remove(session);
+        Ok(())
+    }
+
+    pub fn list_sessions(&self) -> String {
+        if self.sessions.is_empty() {
+            return String::from("No sessions yet.\n");
         }
+        let mut list = String::new();
+        for (index, session) in self.sessions.iter().enumerate() {
+            writeln!(
+                &mut list,
+                "{index:>4}  {}",
+                session.summary(self.shown_in())
+            )
+            .unwrap();
+        }
+        list
+    }
+
+    pub fn list_events(&self, session: usize) -> Result<String, TrkError> {
+        let session = self.session(session)?;
+        Ok(format!(
+            "{}\n{}",
+            session.summary(self.shown_in()),
+            session.list_events(self.shown_in())
+        ))
     }
 
-    pub fn write_files(&self) -> bool {
-        /* TODO: avoid time-of-check-to-time-of-use race risk */
-        self.write_to_json() && self.write_to_html(None) && self.write_last_session_html()
+    /** The content of timesheet.json, one field per line for readable diffs in .trk */
+    pub fn to_json(&self) -> String {
+        let mut serialized =
+            to_string_pretty(&self).expect("Could not write serialized time sheet.");
+        serialized.push('\n');
+        serialized
+    }
+
+    /** Read a timesheet from its JSON representation, upgrading older versions */
+    pub fn parse(serialized: &str) -> Result<Self, TrkError> {
+        let corrupt = |e: serde_json::Error| TrkError::Corrupt(e.to_string());
+        let mut sheet: serde_json::Value = from_str(serialized).map_err(corrupt)?;
+        let current = sheet.get("schema_version") == Some(&SCHEMA_VERSION.into());
+        migrate(&mut sheet)?;
+        serde_json::from_value(sheet).map_err(|e| {
+            /* Parsing the text again tells the line and column, unless it was migrated */
+            match from_str::<Self>(serialized) {
+                Err(positioned) if current => corrupt(positioned),
+                _ => corrupt(e),
+            }
+        })
     }
 
-    /** Return a Some(Timesheet) struct if a timesheet.json file
-     * is present and valid in the .trk directory, and None otherwise.
-     * */
-    pub fn load_from_file() -> Option<Self> {
-        let mut path = env::current_dir().unwrap();
-        loop {
-            path.push(".trk");
-            if path.exists() {
-                env::set_current_dir(&path).unwrap();
-                break;
+    /** Everything which is wrong with the sheet: sessions which break the invariants of
+     * Session::validate(), are out of order, overlap or are running without being the last one.
+     */
+    pub fn check(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        for (index, session) in self.sessions.iter().enumerate() {
+            if let Err(e) = session.validate() {
+                problems.push(format!("session {index}: {e}"));
             }
-            path.pop();
-            if !path.pop() {
-                return None;
+            if session.is_running() && index + 1 < self.sessions.len() {
+                problems.push(format!("session {index} is running but not the last one"));
             }
         }
-
-        path.push("timesheet.json");
-        let file = OpenOptions::new().read(true).open(&path);
-        let result = match file {
-            Ok(mut file) => {
-                let mut serialized = String::new();
-                if let Ok(..) = file.read_to_string(&mut serialized) {
-                    let style: &'static str = include_str!("../../style.css");
-                    let no_git_info_style: &'static str = include_str!("../../no_git_info.css");
-                    let trk_gitignore: &'static str = include_str!("trk_gitignore");
-                    Self::write_stylesheets("style.css", style);
-                    Self::write_stylesheets("no_git_info.css", no_git_info_style);
-                    Self::write_stylesheets(".gitignore", trk_gitignore);
-                    from_str(&serialized).unwrap_or(None)
-                } else {
-                    println!("IO error while reading the timesheet file.");
-                    process::exit(0);
-                }
+        for (index, pair) in self.sessions.windows(2).enumerate() {
+            let (session, next) = (&pair[0], &pair[1]);
+            if next.start <= session.start {
+                problems.push(format!(
+                    "session {} does not start after session {index}",
+                    index + 1
+                ));
+            } else if !session.is_running() && session.overlaps(next) {
+                problems.push(format!("session {} overlaps session {index}", index + 1));
             }
-            Err(..) => None,
-        };
-        path.pop();
-        env::set_current_dir(path).unwrap();
-        result
-    }
-
-    pub fn clear() {
-        /* Try to get user name */
-        let sheet = Self::load_from_file();
-        /* In case there is a sheet, there must also be a name */
-        let name: Option<String> = sheet.map(|s| s.config.user_name.unwrap());
-
-        let path = Path::new("./.trk/timesheet.json");
-        if path.exists() {
-            fs::remove_file(path).unwrap_or_else(|e| {
-                println!("Could not remove sessions file: {e}");
-            });
         }
-        Self::init(name);
+        problems
     }
 
     pub fn timesheet_status(&self) -> String {
         let mut status = format!(
             "Sheet running for {}\n",
-            sec_to_hms_string(get_seconds() - self.start)
+            ms_to_hms_string(self.now().saturating_sub(self.start))
         );
         match self.sessions.len() {
             0 => writeln!(&mut status, "No sessions yet.").unwrap(),
             n => write!(
                 &mut status,
-                "{} session(s) so far.\nLast session:\n{}",
+                "{} session(s) so far, worked for {} and paused for {}.\nLast session:\n{}",
                 n,
-                self.sessions[n - 1].status()
+                ms_to_hms_string(self.work_time()),
+                ms_to_hms_string(self.pause_time()),
+                self.sessions[n - 1].status(self.now())
             )
             .unwrap(),
         };
+        status.push_str(
+            &Breakdown::new(
+                self.sessions.iter(),
+                Filter::default().window(),
+                self.now(),
+                self.shown_in(),
+            )
+            .status(),
+        );
         status
     }
 
     pub fn last_session_status(&self) -> String {
-        let status = self.sessions.last().map(Session::status);
+        let status = self
+            .sessions
+            .last()
+            .map(|session| session.status(self.now()));
         status.unwrap_or_else(|| String::from("No session yet."))
     }
 
-    fn open_local_html(filename: &str) {
-        let file_url = match env::current_dir() {
-            Ok(dir) => {
-                if let Some(path) = dir.join(filename).to_str() {
-                    format!("file://{path}")
-                } else {
-                    println!("Invalid filename: {filename}.");
-                    process::exit(0)
-                }
-            }
-            Err(e) => {
-                println!("Couldn't obtain current directory: {e}");
-                process::exit(0)
-            }
-        };
-        match Url::parse(&file_url) {
-            Ok(url) => url.open(),
-            Err(e) => println!("Couldn't open file: {e}"),
-        }
+    pub fn show_commits(&mut self, on_off: bool) {
+        self.config.show_commits = on_off;
     }
 
-    pub fn report_last_session(&self) {
-        self.write_to_html(None);
-        Self::open_local_html("session.html");
+    pub fn set_repo_url(&mut self, repo: String) {
+        let repo = if repo.is_empty() { None } else { Some(repo) };
+        self.config.repository = repo;
     }
 
-    pub fn report_sheet(&self, ago: Option<u64>) {
-        self.write_to_html(ago);
-        Self::open_local_html("timesheet.html");
-        /* Leave complete sheet html */
-        self.write_to_html(None);
+    pub fn user_name(&self) -> Option<&str> {
+        self.config.user_name.as_deref()
     }
 
-    pub fn show_commits(&mut self, on_off: bool) {
-        self.config.show_commits = on_off;
+    /** None goes back to showing sessions in the timezone they were recorded in */
+    pub fn set_timezone(&mut self, timezone: Option<Tz>) {
+        self.config.timezone = timezone;
     }
 
-    pub fn set_repo_url(&mut self, repo: String) {
-        let repo = if repo.is_empty() { None } else { Some(repo) };
-        self.config.repository = repo;
+    pub fn set_commit_url(&mut self, template: String) {
+        let template = if template.is_empty() {
+            None
+        } else {
+            Some(template)
+        };
+        self.config.commit_url = template;
     }
 
     pub fn pause_time(&self) -> u64 {
         self.sessions
             .iter()
-            .fold(0, |total, session| total + session.pause_time())
+            .fold(0, |total, session| total + session.pause_time(self.now()))
     }
 
     pub fn work_time(&self) -> u64 {
         self.sessions
             .iter()
-            .fold(0, |total, session| total + session.work_time())
+            .fold(0, |total, session| total + session.work_time(self.now()))
     }
 
-    fn to_html(&self, ago: Option<u64>) -> String {
-        let timestamp = ago.unwrap_or(self.start);
-        let sessions_html = self
-            .sessions
+    fn filtered<'a>(&'a self, filter: &'a Filter) -> impl Iterator<Item = &'a Session> {
+        self.sessions
             .iter()
-            .filter(|s| s.start > timestamp)
-            .map(Session::to_html)
-            .map(|s| format!("{s}<hr>"))
-            .collect::<String>();
-        const STYLE: &str = r#"<link rel="stylesheet" type="text/css" href=".trk/style.css">"#;
-        const NO_GIT: &str =
-            r#"<link rel="stylesheet" type="text/css" href=".trk/no_git_info.css">"#;
-        let stylesheets = if self.config.show_commits {
-            format!("{STYLE}\n")
+            .filter(|session| filter.matches(session))
+    }
+
+    /* Work and pause time of the sessions selected by the filter, only counting its window */
+    fn filtered_times(&self, filter: &Filter, now: u64) -> (u64, u64) {
+        let (since, until) = filter.window();
+        self.filtered(filter)
+            .fold((0, 0), |(work_time, pause_time), session| {
+                (
+                    work_time + session.work_time_between(since, until, now),
+                    pause_time + session.pause_time_between(since, until, now),
+                )
+            })
+    }
+
+    /** What the timesheet.html template gets for the sessions selected by the filter */
+    pub fn html_context(&self, filter: &Filter) -> Value {
+        let now = self.now();
+        let tz = self.shown_in();
+        let (work_time, pause_time) = self.filtered_times(filter, now);
+        let commits: CommitStats = self.filtered(filter).map(Session::commit_stats).sum();
+        context! {
+            user_name => self.config.user_name,
+            show_commits => self.config.show_commits,
+            sessions => self
+                .filtered(filter)
+                .map(|session| session.html_context(&self.config, now, tz))
+                .collect::<Vec<Value>>(),
+            work_time => ms_to_hms_string(work_time),
+            pause_time => ms_to_hms_string(pause_time),
+            commits => commits.to_string(),
+            commit_count => commits.commits,
+            breakdown => Breakdown::new(self.filtered(filter), filter.window(), now, tz)
+                .html_context(),
+        }
+    }
+
+    /** What the session.html template gets, None without sessions */
+    pub fn last_session_html_context(&self) -> Option<Value> {
+        let session = self.sessions.last()?;
+        Some(context! {
+            user_name => self.config.user_name,
+            show_commits => self.config.show_commits,
+            session => session.html_context(&self.config, self.now(), self.shown_in()),
+        })
+    }
+
+    /** Wraps a LaTeX body into a standalone document.
+     * \ifshowcommits plays the role of no_git_info.css in the html report.
+     */
+    fn tex_document(&self, title: &str, body: &str, now: u64, tz: Option<Tz>) -> String {
+        let user_name = self.config.user_name.as_deref().unwrap_or_default();
+        let show_commits = if self.config.show_commits {
+            r"\showcommitstrue"
         } else {
-            format!("{STYLE}\n{NO_GIT}\n")
+            r"\showcommitsfalse"
         };
+        format!(
+            r"\documentclass[a4paper,11pt]{{article}}
+\usepackage[utf8]{{inputenc}}
+\usepackage[T1]{{fontenc}}
+\usepackage[margin=2cm]{{geometry}}
+\usepackage{{longtable}}
+\usepackage{{booktabs}}
+\newif\ifshowcommits
+{}
 
-        let mut html = format!(
-            r#"<!DOCTYPE html>
-<html>
-    <head>
-        {}
-        <title>{} for {}</title>
-    </head>
-    <body>
-    {}"#,
-            stylesheets, "Timesheet", "Rafael Bachmann", sessions_html
-        );
+\title{{{} for {}}}
+\date{{{}}}
+
+\begin{{document}}
+\maketitle
+
+{}
+\end{{document}}
+",
+            show_commits,
+            title,
+            tex_escape(user_name),
+            ts_to_date(now, tz.unwrap_or_else(system_timezone)),
+            body
+        )
+    }
+
+    /** A LaTeX document of the last session */
+    pub fn last_session_tex(&self) -> Result<String, TrkError> {
+        let session = self.sessions.last().ok_or(TrkError::NoSession)?;
+        let (now, tz) = (self.now(), self.shown_in());
+        Ok(self.tex_document("Session", &session.to_tex(now, tz), now, tz))
+    }
+}
+
+/** A LaTeX document of the sessions selected by Timesheet::select, all unless one was */
+impl HasTEX for Timesheet {
+    fn to_tex(&self, now: u64, tz: Option<Tz>) -> String {
+        let filter = &self.selected;
+        let (work_time, pause_time) = self.filtered_times(filter, now);
+        let mut body = self
+            .filtered(filter)
+            .map(|session| session.to_tex(now, tz))
+            .collect::<Vec<String>>()
+            .join("\n\\bigskip\n\n");
 
         write!(
-            &mut html,
-            r#"<section class="summary">
-    <p>Worked for {}</p>
-    <p>Paused for {}</p>
-</div></section>"#,
-            sec_to_hms_string(self.work_time()),
-            sec_to_hms_string(self.pause_time())
+            &mut body,
+            r"
+\section*{{Summary}}
+\begin{{tabular}}{{@{{}}ll@{{}}}}
+Worked for & {} \\
+Paused for & {} \\
+\end{{tabular}}
+",
+            ms_to_hms_string(work_time),
+            ms_to_hms_string(pause_time)
         )
         .unwrap();
-        write!(&mut html, "</body>\n</html>").unwrap();
-        html
+
+        body.push_str(&Breakdown::new(self.filtered(filter), filter.window(), now, tz).to_tex());
+        self.tex_document("Timesheet", &body, now, tz)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::FakeClock;
+    use crate::sheet::session::Event;
+
+    const START: u64 = 1_700_000_000_000;
+    const SECOND: u64 = 1000;
+    const MINUTE: u64 = 60 * SECOND;
+
+    fn sheet() -> (Timesheet, Arc<FakeClock>) {
+        let clock = Arc::new(FakeClock::new(START));
+        let sheet = Timesheet::with_clock(String::from("tester"), clock.clone());
+        (sheet, clock)
+    }
+
+    #[test]
+    fn events_without_time_happen_now() {
+        let (mut sheet, clock) = sheet();
+        clock.advance(MINUTE);
+        sheet.new_session(None).unwrap();
+        clock.advance(30 * MINUTE);
+        sheet.pause(None, None).unwrap();
+        clock.advance(10 * MINUTE);
+        sheet.resume(None).unwrap();
+        clock.advance(20 * MINUTE);
+
+        let session = &sheet.sessions()[0];
+        assert_eq!(session.start, START + MINUTE);
+        let times: Vec<u64> = session.events().iter().map(Event::timestamp).collect();
+        assert_eq!(times, [START + 31 * MINUTE, START + 41 * MINUTE]);
+        assert_eq!(sheet.pause_time(), 10 * MINUTE);
+        assert_eq!(sheet.work_time(), 50 * MINUTE);
+        assert!(sheet
+            .last_session_status()
+            .contains("Total work time:  50 minutes"));
+
+        /* Running sessions keep counting */
+        clock.advance(10 * MINUTE);
+        assert_eq!(sheet.work_time(), 60 * MINUTE);
+    }
+
+    #[test]
+    fn events_in_the_same_second() {
+        let (mut sheet, clock) = sheet();
+        sheet.new_session(None).unwrap();
+        clock.advance(300);
+        sheet.note(None, String::from("first"), vec![]).unwrap();
+        sheet.add_commit(String::from("abc1234"), None).unwrap();
+        clock.advance(300);
+        sheet.end_session(None).unwrap();
+
+        assert_eq!(sheet.sessions()[0].events().len(), 2);
+        assert_eq!(sheet.sessions()[0].end, START + 600);
+        assert_eq!(sheet.work_time(), 600);
+        assert!(sheet.check().is_empty());
+    }
+
+    #[test]
+    fn end_session_while_paused() {
+        let (mut sheet, clock) = sheet();
+        clock.advance(MINUTE);
+        sheet.new_session(None).unwrap();
+        clock.advance(60 * MINUTE);
+        sheet.pause(None, Some(String::from("lunch"))).unwrap();
+        clock.advance(30 * MINUTE);
+        sheet.end_session(None).unwrap();
+
+        let session = &sheet.sessions()[0];
+        assert!(!session.is_running());
+        assert_eq!(session.end, START + 91 * MINUTE);
+        assert_eq!(sheet.pause_time(), 30 * MINUTE);
+        assert_eq!(sheet.work_time(), 60 * MINUTE);
+        clock.advance(120 * MINUTE);
+        assert_eq!(sheet.pause_time(), 30 * MINUTE);
+        assert!(matches!(sheet.end_session(None), Err(TrkError::NotRunning)));
+        assert!(sheet.check().is_empty());
+    }
+
+    #[test]
+    fn retroactive_sessions() {
+        let (mut sheet, clock) = sheet();
+        clock.set(START + 24 * 60 * MINUTE);
+        assert!(matches!(
+            sheet.new_session(Some(START - 1)),
+            Err(TrkError::InvalidTimestamp)
+        ));
+        sheet.new_session(Some(START + 60 * MINUTE)).unwrap();
+        sheet.pause(Some(START + 90 * MINUTE), None).unwrap();
+        sheet.resume(Some(START + 100 * MINUTE)).unwrap();
+        assert!(matches!(
+            sheet.note(Some(START + 95 * MINUTE), String::from("late"), vec![]),
+            Err(TrkError::InvalidTimestamp)
+        ));
+        sheet.end_session(Some(START + 120 * MINUTE)).unwrap();
+
+        /* The next session may begin when this one ended, not before */
+        assert!(matches!(
+            sheet.new_session(Some(START + 120 * MINUTE - 1)),
+            Err(TrkError::InvalidTimestamp)
+        ));
+        sheet.new_session(Some(START + 120 * MINUTE)).unwrap();
+
+        assert_eq!(sheet.sessions().len(), 2);
+        assert_eq!(sheet.sessions()[0].pause_time(sheet.now()), 10 * MINUTE);
+        assert_eq!(sheet.sessions()[0].work_time(sheet.now()), 50 * MINUTE);
+        assert!(sheet.check().is_empty());
+    }
+
+    #[test]
+    fn only_one_session_runs() {
+        let (mut sheet, clock) = sheet();
+        clock.advance(MINUTE);
+        sheet.new_session(None).unwrap();
+        clock.advance(MINUTE);
+        assert!(matches!(
+            sheet.new_session(None),
+            Err(TrkError::SessionRunning)
+        ));
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let (mut sheet, clock) = sheet();
+        clock.advance(MINUTE);
+        sheet.new_session(None).unwrap();
+        clock.advance(MINUTE);
+        sheet
+            .note(None, String::from("hello"), vec![String::from("docs")])
+            .unwrap();
+        let serialized = sheet.to_json();
+        let parsed = Timesheet::parse(&serialized).unwrap();
+        assert_eq!(parsed.to_json(), serialized);
+        assert_eq!(parsed.sessions()[0].events()[0].note(), Some("hello"));
+    }
+
+    #[test]
+    fn seconds_of_version_1_become_milliseconds() {
+        let version_1 = r#"{
+            "schema_version": 1,
+            "start": 1700000000,
+            "end": 1700003602,
+            "config": {"show_commits": true, "repository": null, "user_name": "tester"},
+            "sessions": [{
+                "start": 1700000060,
+                "end": 1700003601,
+                "running": false,
+                "branches": [],
+                "events": [
+                    {"timestamp": 1700000600, "note": null, "ev_ty": "Pause"},
+                    {"timestamp": 1700000900, "note": null, "ev_ty": "Resume"},
+                    {"timestamp": 1700001000, "note": null, "ev_ty": {"Commit": {
+                        "hash": "abc1234",
+                        "details": {"author": "t <t@t>", "date": 1700000999, "subject": "s",
+                            "body": "", "files": [], "insertions": 1, "deletions": 0}
+                    }}}
+                ]
+            }]
+        }"#;
+        let sheet = Timesheet::parse(version_1).unwrap();
+        let session = &sheet.sessions()[0];
+        assert_eq!(session.start, 1_700_000_060_000);
+        assert_eq!(session.end, 1_700_003_600_000);
+        assert_eq!(session.events()[0].timestamp(), 1_700_000_600_000);
+        assert_eq!(session.pause_time(0), 5 * MINUTE);
+        assert_eq!(session.work_time(0), 54 * MINUTE);
+        match session.events()[2].ev_ty() {
+            EventType::Commit {
+                details: Some(details),
+                ..
+            } => assert_eq!(details.date, 1_700_000_999_000),
+            _ => panic!("not a commit with details"),
+        }
+        assert!(sheet.check().is_empty());
+        assert!(sheet
+            .to_json()
+            .contains(&format!("\"schema_version\": {SCHEMA_VERSION}")));
+    }
+
+    #[test]
+    fn html_context_of_running_session() {
+        let (mut sheet, clock) = sheet();
+        clock.advance(MINUTE);
+        sheet.new_session(None).unwrap();
+        clock.advance(90 * MINUTE);
+        let context = sheet.html_context(&Filter::default());
+        assert_eq!(
+            context.get_attr("work_time").unwrap().to_string(),
+            "1 hours and 30 minutes"
+        );
+        assert_eq!(context.get_attr("user_name").unwrap().to_string(), "tester");
+        assert!(sheet.last_session_html_context().is_some());
+    }
+
+    /* 00:30 on the 29th in Berlin, where the clocks go forward at 02:00. Still the 28th in UTC */
+    const DST_CHANGE: u64 = 1_774_740_600_000;
+
+    #[test]
+    fn days_are_those_of_the_timezone() {
+        let (mut sheet, clock) = sheet();
+        clock.set(DST_CHANGE + 180 * MINUTE);
+        sheet.set_timezone(Some(Tz::Europe__Berlin));
+        sheet.new_session(Some(DST_CHANGE)).unwrap();
+        sheet.end_session(Some(DST_CHANGE + 120 * MINUTE)).unwrap();
+        assert_eq!(sheet.sessions()[0].timezone(None), Tz::Europe__Berlin);
+
+        /* Three hours on the wall clock, but two worked */
+        assert!(sheet
+            .list_sessions()
+            .contains("2026-03-29, 00:30 - 2026-03-29, 03:30"));
+        assert!(sheet
+            .timesheet_status()
+            .contains(&format!("    {:<20} 2 hours\n", "2026-03-29")));
+
+        sheet.show_in(Tz::UTC);
+        assert!(sheet
+            .list_sessions()
+            .contains("2026-03-28, 23:30 - 2026-03-29, 01:30"));
+        /* Which is past midnight in UTC */
+        let status = sheet.timesheet_status();
+        assert!(status.contains(&format!("    {:<20} 30 minutes\n", "2026-03-28")));
+        assert!(status.contains(&format!(
+            "    {:<20} 1 hours and 30 minutes\n",
+            "2026-03-29"
+        )));
+
+        /* Without a configured timezone sessions keep the one they were recorded in */
+        let mut sheet = Timesheet::parse(&sheet.to_json()).unwrap();
+        sheet.set_timezone(None);
+        assert!(sheet
+            .list_sessions()
+            .contains("2026-03-29, 00:30 - 2026-03-29, 03:30"));
+    }
+
+    /* Key, work time and pause time of each row of one table of the breakdown */
+    fn breakdown_rows(context: &Value, title: &str) -> Vec<(String, String, String)> {
+        let group = context
+            .get_attr("breakdown")
+            .unwrap()
+            .try_iter()
+            .unwrap()
+            .find(|group| group.get_attr("title").unwrap().as_str() == Some(title))
+            .unwrap();
+        let field = |row: &Value, name: &str| row.get_attr(name).unwrap().to_string();
+        group
+            .get_attr("rows")
+            .unwrap()
+            .try_iter()
+            .unwrap()
+            .map(|row| {
+                (
+                    field(&row, "key"),
+                    field(&row, "work_time"),
+                    field(&row, "pause_time"),
+                )
+            })
+            .collect()
+    }
+
+    /* 22:00 on the 28th to 04:00 on the 29th in Berlin, five hours as an hour is skipped,
+     * with a pause from 00:30 to 01:00 */
+    fn night_session() -> Timesheet {
+        let (mut sheet, clock) = sheet();
+        clock.set(DST_CHANGE + 180 * MINUTE);
+        sheet.set_timezone(Some(Tz::Europe__Berlin));
+        sheet.new_session(Some(DST_CHANGE - 150 * MINUTE)).unwrap();
+        sheet.pause(Some(DST_CHANGE), None).unwrap();
+        sheet.resume(Some(DST_CHANGE + 30 * MINUTE)).unwrap();
+        sheet.end_session(Some(DST_CHANGE + 150 * MINUTE)).unwrap();
+        sheet
+    }
+
+    #[test]
+    fn sessions_past_midnight_count_for_both_days() {
+        let sheet = night_session();
+        let context = sheet.html_context(&Filter::default());
+        assert_eq!(
+            breakdown_rows(&context, "Day"),
+            [
+                ("2026-03-28".into(), "2 hours".into(), "0 seconds".into()),
+                (
+                    "2026-03-29".into(),
+                    "2 hours and 30 minutes".into(),
+                    "30 minutes".into()
+                ),
+            ]
+        );
+        assert_eq!(
+            breakdown_rows(&context, "Week"),
+            [(
+                "2026-W13".into(),
+                "4 hours and 30 minutes".into(),
+                "30 minutes".into()
+            )]
+        );
+    }
+
+    #[test]
+    fn since_counts_what_is_after_it() {
+        let sheet = night_session();
+        /* Midnight in Berlin */
+        let filter = Filter {
+            since: Some(DST_CHANGE - 30 * MINUTE),
+            ..Filter::default()
+        };
+        let context = sheet.html_context(&filter);
+        assert_eq!(context.get_attr("sessions").unwrap().len(), Some(1));
+        assert_eq!(
+            context.get_attr("work_time").unwrap().to_string(),
+            "2 hours and 30 minutes"
+        );
+        assert_eq!(
+            breakdown_rows(&context, "Day"),
+            [(
+                "2026-03-29".into(),
+                "2 hours and 30 minutes".into(),
+                "30 minutes".into()
+            )]
+        );
+
+        let filter = Filter {
+            since: Some(DST_CHANGE + 150 * MINUTE),
+            ..Filter::default()
+        };
+        assert_eq!(
+            sheet
+                .html_context(&filter)
+                .get_attr("sessions")
+                .unwrap()
+                .len(),
+            Some(0)
+        );
+    }
+
+    /* The sheet as another machine has it, with a clock of its own */
+    fn machine(sheet: &Timesheet, now: u64) -> (Timesheet, Arc<FakeClock>) {
+        let clock = Arc::new(FakeClock::new(now));
+        let mut copy = Timesheet::parse(&sheet.to_json()).unwrap();
+        copy.set_clock(clock.clone());
+        (copy, clock)
+    }
+
+    fn starts(sheet: &Timesheet) -> Vec<u64> {
+        sheet.sessions().iter().map(|s| s.start).collect()
+    }
+
+    #[test]
+    fn merging_a_sheet_with_itself_changes_nothing() {
+        let (mut sheet, clock) = sheet();
+        sheet.new_session(Some(START)).unwrap();
+        sheet.pause(Some(START + 20 * MINUTE), None).unwrap();
+        sheet.resume(Some(START + 30 * MINUTE)).unwrap();
+        clock.set(START + 40 * MINUTE);
+        sheet.add_commit(String::from("abc1234"), None).unwrap();
+        sheet
+            .note(None, String::from("done"), vec![String::from("docs")])
+            .unwrap();
+        sheet.end_session(Some(START + 60 * MINUTE)).unwrap();
+        sheet.new_session(Some(START + 90 * MINUTE)).unwrap();
+
+        let serialized = sheet.to_json();
+        let (same, _) = machine(&sheet, START + 90 * MINUTE);
+        let merged = sheet.merge(same).unwrap();
+        assert_eq!(merged.to_json(), serialized);
+    }
+
+    #[test]
+    fn sessions_of_two_machines_are_interleaved() {
+        let (mut sheet, _) = sheet();
+        sheet.new_session(Some(START)).unwrap();
+        sheet.end_session(Some(START + 60 * MINUTE)).unwrap();
+        let (mut laptop, _) = machine(&sheet, START + 300 * MINUTE);
+        let (mut desktop, _) = machine(&sheet, START + 300 * MINUTE);
+        laptop.new_session(Some(START + 90 * MINUTE)).unwrap();
+        laptop.end_session(Some(START + 120 * MINUTE)).unwrap();
+        laptop.new_session(Some(START + 200 * MINUTE)).unwrap();
+        laptop.end_session(Some(START + 240 * MINUTE)).unwrap();
+        desktop.new_session(Some(START + 130 * MINUTE)).unwrap();
+        desktop.end_session(Some(START + 180 * MINUTE)).unwrap();
+
+        let merged = laptop.merge(desktop).unwrap();
+        assert_eq!(
+            starts(&merged),
+            [
+                START,
+                START + 90 * MINUTE,
+                START + 130 * MINUTE,
+                START + 200 * MINUTE
+            ]
+        );
+        assert_eq!(merged.work_time(), 180 * MINUTE);
+        assert!(merged.check().is_empty());
+    }
+
+    #[test]
+    fn a_session_continued_on_both_machines_has_all_events_once() {
+        let (mut sheet, _) = sheet();
+        sheet.new_session(Some(START)).unwrap();
+        sheet
+            .note(Some(START + 10 * MINUTE), String::from("start"), vec![])
+            .unwrap();
+        let (mut laptop, laptop_clock) = machine(&sheet, START + 30 * MINUTE);
+        let (mut desktop, desktop_clock) = machine(&sheet, START + 30 * MINUTE);
+        laptop.pause(Some(START + 20 * MINUTE), None).unwrap();
+        laptop.resume(Some(START + 30 * MINUTE)).unwrap();
+        laptop_clock.set(START + 60 * MINUTE);
+        laptop.end_session(None).unwrap();
+        desktop_clock.set(START + 40 * MINUTE);
+        desktop.add_commit(String::from("abc1234"), None).unwrap();
+        desktop.note(None, String::from("pushed"), vec![]).unwrap();
+
+        let merged = laptop.merge(desktop).unwrap();
+        assert_eq!(merged.sessions().len(), 1);
+        let session = &merged.sessions()[0];
+        let times: Vec<u64> = session.events().iter().map(Event::timestamp).collect();
+        assert_eq!(
+            times,
+            [
+                START + 10 * MINUTE,
+                START + 20 * MINUTE,
+                START + 30 * MINUTE,
+                START + 40 * MINUTE,
+                START + 40 * MINUTE
+            ]
+        );
+        assert!(!session.is_running());
+        assert_eq!(session.end, START + 60 * MINUTE);
+        assert_eq!(merged.work_time(), 50 * MINUTE);
+        assert!(merged.check().is_empty());
+    }
+
+    #[test]
+    fn conflicting_sessions_are_not_merged() {
+        let (mut sheet, _) = sheet();
+        sheet.new_session(Some(START)).unwrap();
+        let (mut laptop, _) = machine(&sheet, START + 300 * MINUTE);
+        let (mut desktop, _) = machine(&sheet, START + 300 * MINUTE);
+        laptop.end_session(Some(START + 60 * MINUTE)).unwrap();
+        laptop.new_session(Some(START + 100 * MINUTE)).unwrap();
+        laptop.end_session(Some(START + 200 * MINUTE)).unwrap();
+        desktop.end_session(Some(START + 60 * MINUTE)).unwrap();
+        desktop.new_session(Some(START + 150 * MINUTE)).unwrap();
+        desktop.end_session(Some(START + 250 * MINUTE)).unwrap();
+
+        let before = laptop.to_json();
+        let (copy, _) = machine(&laptop, START + 300 * MINUTE);
+        match copy.merge(desktop) {
+            Err(TrkError::InvalidArgument(report)) => {
+                assert!(report.contains("overlaps session 1"), "{report}");
+            }
+            other => panic!("merged overlapping sessions: {other:?}"),
+        }
+        assert_eq!(laptop.to_json(), before);
+
+        /* Two pauses in a row can't be one session either */
+        let (mut laptop, _) = machine(&sheet, START + 300 * MINUTE);
+        let (mut desktop, _) = machine(&sheet, START + 300 * MINUTE);
+        laptop.pause(Some(START + 20 * MINUTE), None).unwrap();
+        desktop.pause(Some(START + 25 * MINUTE), None).unwrap();
+        match laptop.merge(desktop) {
+            Err(TrkError::InvalidArgument(report)) => {
+                assert!(report.contains("session 0"), "{report}");
+            }
+            other => panic!("merged inconsistent sessions: {other:?}"),
+        }
+    }
+
+    /* A finished session with a note, a pause with two notes and a commit */
+    fn listed_sheet() -> Timesheet {
+        let (mut sheet, clock) = sheet();
+        sheet.show_in(Tz::UTC);
+        sheet.new_session(Some(START)).unwrap();
+        sheet
+            .note(Some(START + 10 * MINUTE), String::from("plan"), vec![])
+            .unwrap();
+        sheet
+            .pause(Some(START + 20 * MINUTE), Some(String::from("lunch")))
+            .unwrap();
+        sheet
+            .note(Some(START + 25 * MINUTE), String::from("soup"), vec![])
+            .unwrap();
+        sheet.resume(Some(START + 40 * MINUTE)).unwrap();
+        clock.set(START + 50 * MINUTE);
+        sheet.add_commit(String::from("abc1234"), None).unwrap();
+        sheet.end_session(Some(START + 60 * MINUTE)).unwrap();
+        sheet
+    }
+
+    #[test]
+    fn lists_end_with_a_newline() {
+        let (sheet, _) = sheet();
+        assert_eq!(sheet.list_sessions(), "No sessions yet.\n");
+        let sheet = listed_sheet();
+        assert!(sheet.list_sessions().starts_with("   0  "));
+        assert!(sheet.list_sessions().ends_with('\n'));
+        assert!(matches!(
+            sheet.list_events(1),
+            Err(TrkError::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn pause_notes_are_listed_on_lines_of_their_own() {
+        let list = listed_sheet().list_events(0).unwrap();
+        let lines: Vec<&str> = list.lines().skip(1).collect();
+        assert_eq!(lines[0], "   0  2023-11-14, 22:23  Note    plan");
+        assert_eq!(lines[1], "   1  2023-11-14, 22:33  Pause   lunch");
+        assert_eq!(lines[2], "                                 soup");
+        assert_eq!(lines[3], "   2  2023-11-14, 22:53  Resume  ");
+        assert!(!list.contains("<br>"));
+    }
+
+    #[test]
+    fn edits_which_break_the_session_are_refused() {
+        let mut sheet = listed_sheet();
+        let before = sheet.to_json();
+        let refused = |result: Result<(), TrkError>| match result {
+            Err(TrkError::Inconsistent(problem)) => problem,
+            other => panic!("not refused: {other:?}"),
+        };
+
+        /* The note moved after the pause would be out of order */
+        let problem = refused(sheet.edit_event(0, 0, Some(START + 30 * MINUTE), None, None));
+        assert!(problem.starts_with("session 0: event"), "{problem}");
+        refused(sheet.edit_event(0, 2, None, None, Some(EventType::Pause)));
+        refused(sheet.edit_event(0, 0, Some(START - MINUTE), None, None));
+        refused(sheet.edit_event(0, 0, None, Some(None), None));
+        assert_eq!(sheet.to_json(), before);
+
+        sheet
+            .edit_event(
+                0,
+                0,
+                Some(START + 5 * MINUTE),
+                Some(Some(String::from("plan it"))),
+                None,
+            )
+            .unwrap();
+        let note = &sheet.sessions()[0].events()[0];
+        assert_eq!(note.timestamp(), START + 5 * MINUTE);
+        assert_eq!(note.note(), Some("plan it"));
+    }
+
+    #[test]
+    fn invalid_edits_and_deletes() {
+        let mut sheet = listed_sheet();
+        let invalid =
+            |result: Result<(), TrkError>| matches!(result, Err(TrkError::InvalidArgument(_)));
+        assert!(invalid(sheet.edit_event(1, 0, None, None, None)));
+        assert!(invalid(sheet.edit_event(0, 4, None, None, None)));
+        assert!(invalid(sheet.delete_event(1, 0)));
+        assert!(invalid(sheet.delete_event(0, 4)));
+        assert!(invalid(sheet.edit_event(
+            0,
+            3,
+            None,
+            None,
+            Some(EventType::Note)
+        )));
+        assert_eq!(sheet.sessions()[0].events().len(), 4);
+    }
+
+    #[test]
+    fn deleting_half_a_pause_deletes_all_of_it() {
+        let mut sheet = listed_sheet();
+        sheet.delete_event(0, 2).unwrap();
+        let types: Vec<&str> = sheet.sessions()[0]
+            .events()
+            .iter()
+            .map(|event| event.ev_ty().name())
+            .collect();
+        assert_eq!(types, ["Note", "Commit"]);
+        assert_eq!(sheet.pause_time(), 0);
+        sheet.delete_event(0, 0).unwrap();
+        assert_eq!(sheet.sessions()[0].events().len(), 1);
+        assert!(sheet.check().is_empty());
+    }
+
+    #[test]
+    fn backfill_skips_recorded_commits_and_pauses() {
+        let mut sheet = listed_sheet();
+        let commits = vec![
+            /* The full hash of the commit recorded with its short hash */
+            (String::from("abc1234def"), START + 50 * MINUTE),
+            (String::from("ba5eba11"), START + 30 * MINUTE),
+            (String::from("0ff1ce"), START - MINUTE),
+            (String::from("c0ffee"), START + 45 * MINUTE),
+            (String::from("c0ffee"), START + 45 * MINUTE),
+        ];
+        let backfill = sheet.backfill(commits, |_| None);
+        assert_eq!(backfill.inserted, 1);
+        assert_eq!(backfill.recorded, 2);
+        assert_eq!(backfill.outside, 1);
+        assert_eq!(backfill.refused, 1);
+
+        let session = &sheet.sessions()[0];
+        assert!(session.has_commit("c0ffee"));
+        assert!(!session.has_commit("ba5eba11"));
+        let commits = session
+            .events()
+            .iter()
+            .filter(|event| event.ev_ty().name() == "Commit")
+            .count();
+        assert_eq!(commits, 2);
+        assert!(sheet.check().is_empty());
+    }
+
+    #[test]
+    fn status_with_a_clock_behind_the_sheet() {
+        let (sheet, clock) = sheet();
+        clock.set(START - MINUTE);
+        assert!(sheet
+            .timesheet_status()
+            .starts_with("Sheet running for 0 seconds"));
     }
 }
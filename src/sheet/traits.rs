@@ -1,7 +1,15 @@
+use chrono_tz::Tz;
+use minijinja::Value;
+
+use crate::config::Config;
+
+/* now is the time running sessions are shown up to. tz is the timezone times are shown in,
+ * None to show each session in the one it was recorded in. */
 pub trait HasTEX {
-    fn to_tex(&self) -> String;
+    fn to_tex(&self, now: u64, tz: Option<Tz>) -> String;
 }
 
+/* What the html templates get to see of something, see sheet/html.rs */
 pub trait HasHTML {
-    fn to_html(&self) -> String;
+    fn html_context(&self, config: &Config, now: u64, tz: Option<Tz>) -> Value;
 }
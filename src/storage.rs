@@ -0,0 +1,228 @@
+/* Where a timesheet lives on disk.
+ *
+ * A Storage is the directory containing .trk. It reads and writes .trk/timesheet.json,
+ * commits every change to the git repository in .trk and writes the html and LaTeX reports
+ * next to .trk. Nothing depends on the current directory, so several sheets can be used
+ * at once.
+ */
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::config::{SyncConfig, SyncMode};
+use crate::error::TrkError;
+use crate::git::{
+    git_commit_trk, git_init_trk, git_log_trk, git_pull, git_push, git_resolve_trk,
+    git_set_sync_config, git_show_trk, git_sync_config, git_sync_status, Revision, SyncStatus,
+};
+use crate::sheet::filter::Filter;
+use crate::sheet::html::render;
+use crate::sheet::timesheet::Timesheet;
+use crate::util::format_file;
+
+const TRK_DIR: &str = ".trk";
+const SHEET_FILE: &str = "timesheet.json";
+const TEMPLATE_DIR: &str = "templates";
+
+/* Files .trk needs besides the sheet, written if missing */
+const SUPPORT_FILES: [(&str, &str); 3] = [
+    ("style.css", include_str!("../style.css")),
+    ("no_git_info.css", include_str!("../no_git_info.css")),
+    (".gitignore", include_str!("sheet/trk_gitignore")),
+];
+
+pub struct Storage {
+    root: PathBuf,
+}
+
+impl Storage {
+    /** The sheet in root/.trk, which need not exist yet */
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /** The sheet in the nearest directory from start upwards which contains .trk */
+    pub fn discover(start: &Path) -> Result<Self, TrkError> {
+        start
+            .ancestors()
+            .find(|dir| dir.join(TRK_DIR).is_dir())
+            .map(Self::new)
+            .ok_or(TrkError::NoTrkDir)
+    }
+
+    /** The directory containing .trk, where the reports are written */
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn trk_dir(&self) -> PathBuf {
+        self.root.join(TRK_DIR)
+    }
+
+    fn sheet_path(&self) -> PathBuf {
+        self.trk_dir().join(SHEET_FILE)
+    }
+
+    pub fn is_init(&self) -> bool {
+        self.sheet_path().exists()
+    }
+
+    /** Create .trk with its git repository and commit the given empty sheet */
+    pub fn init(&self, sheet: &Timesheet) -> Result<(), TrkError> {
+        if self.is_init() {
+            return Err(TrkError::AlreadyInitialised);
+        }
+        git_init_trk(&self.trk_dir())?;
+        self.save(sheet, "initialise trk")
+    }
+
+    /** Some(Timesheet) if .trk/timesheet.json exists, None if it doesn't,
+     * and TrkError::Corrupt if the file isn't a valid timesheet.
+     */
+    pub fn load(&self) -> Result<Option<Timesheet>, TrkError> {
+        let serialized = match fs::read_to_string(self.sheet_path()) {
+            Ok(serialized) => serialized,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        for (name, content) in SUPPORT_FILES {
+            let path = self.trk_dir().join(name);
+            if !path.exists() {
+                fs::write(path, content)?;
+            }
+        }
+        Timesheet::parse(&serialized).map(Some)
+    }
+
+    /** Write the sheet to .trk/timesheet.json and commit it with the given message.
+     * The file is replaced at once, so it is never half written,
+     * and only if the commit could be created.
+     */
+    pub fn save(&self, sheet: &Timesheet, message: &str) -> Result<(), TrkError> {
+        let serialized = sheet.to_json();
+        git_commit_trk(&self.trk_dir(), &serialized, message, || {
+            let temporary = self.trk_dir().join(format!("{SHEET_FILE}.tmp"));
+            fs::write(&temporary, &serialized)?;
+            fs::rename(temporary, self.sheet_path())?;
+            Ok(())
+        })
+    }
+
+    /* A file in root, tidied if html-tidy is installed */
+    fn write_report(&self, name: &str, content: &str) -> Result<PathBuf, TrkError> {
+        let path = self.root.join(name);
+        fs::write(&path, content)?;
+        if name.ends_with(".html") {
+            format_file(&path);
+        }
+        Ok(path)
+    }
+
+    /* The templates in .trk/templates replace the compiled in ones */
+    fn render(&self, name: &str, context: minijinja::Value) -> Result<String, TrkError> {
+        render(&self.trk_dir().join(TEMPLATE_DIR), name, context)
+    }
+
+    /** Write timesheet.html with the sessions selected by the filter and return its path */
+    pub fn write_sheet_html(
+        &self,
+        sheet: &Timesheet,
+        filter: &Filter,
+    ) -> Result<PathBuf, TrkError> {
+        let html = self.render("timesheet.html", sheet.html_context(filter))?;
+        self.write_report("timesheet.html", &html)
+    }
+
+    /** Write session.html for the last session and return its path */
+    pub fn write_session_html(&self, sheet: &Timesheet) -> Result<PathBuf, TrkError> {
+        let context = sheet
+            .last_session_html_context()
+            .ok_or(TrkError::NoSession)?;
+        let html = self.render("session.html", context)?;
+        self.write_report("session.html", &html)
+    }
+
+    /** Regenerate timesheet.html and session.html, as after every change */
+    pub fn write_reports(&self, sheet: &Timesheet) -> Result<(), TrkError> {
+        self.write_sheet_html(sheet, &Filter::default())?;
+        match self.write_session_html(sheet) {
+            Err(TrkError::NoSession) => Ok(()),
+            result => result.map(|_| ()),
+        }
+    }
+
+    /** Write a LaTeX report like timesheet.tex and return its path */
+    pub fn write_tex(&self, name: &str, tex: &str) -> Result<PathBuf, TrkError> {
+        self.write_report(name, tex)
+    }
+
+    /** History of the sheet, newest first */
+    pub fn history(&self) -> Result<Vec<Revision>, TrkError> {
+        git_log_trk(&self.trk_dir())
+    }
+
+    /** Full hash of a revision like HEAD~2 or an abbreviated hash */
+    pub fn resolve(&self, revision: &str) -> Result<String, TrkError> {
+        git_resolve_trk(&self.trk_dir(), revision)
+    }
+
+    /** The sheet as it was at the given revision */
+    pub fn revision(&self, revision: &str) -> Result<Timesheet, TrkError> {
+        Timesheet::parse(&self.revision_json(revision)?)
+    }
+
+    /** Content of timesheet.json at the given revision */
+    pub fn revision_json(&self, revision: &str) -> Result<String, TrkError> {
+        git_show_trk(&self.trk_dir(), revision)
+    }
+
+    /** How this machine syncs the sheet, kept in .trk/.git/config and not synced itself */
+    pub fn sync_config(&self) -> Result<SyncConfig, TrkError> {
+        git_sync_config(&self.trk_dir())
+    }
+
+    /** Set how this machine syncs the sheet.
+     * Remote and branch are kept if not given and reset by an empty name.
+     */
+    pub fn set_sync(
+        &self,
+        mode: SyncMode,
+        remote: Option<String>,
+        branch: Option<String>,
+    ) -> Result<(), TrkError> {
+        let non_empty = |name: String| if name.is_empty() { None } else { Some(name) };
+        let mut sync = self.sync_config().unwrap_or_default();
+        sync.mode = mode;
+        if let Some(remote) = remote {
+            sync.remote = non_empty(remote);
+        }
+        if let Some(branch) = branch {
+            sync.branch = non_empty(branch);
+        }
+        git_set_sync_config(&self.trk_dir(), &sync)
+    }
+
+    /** Fast-forward to the remote, merging with merge if both have changed.
+     * Returns whether the sheet changed. Unless git::limit_timeouts was called,
+     * an unreachable remote takes as long as libgit2's default timeouts.
+     */
+    pub fn pull(
+        &self,
+        sync: &SyncConfig,
+        merge: impl FnOnce(&str, &str) -> Result<String, TrkError>,
+    ) -> Result<bool, TrkError> {
+        git_pull(&self.trk_dir(), sync, merge)
+    }
+
+    pub fn push(&self, sync: &SyncConfig) -> Result<(), TrkError> {
+        git_push(&self.trk_dir(), sync)
+    }
+
+    /** How far the sheet is ahead of or behind the remote as of the last sync,
+     * None without a remote
+     */
+    pub fn sync_status(&self, sync: &SyncConfig) -> Result<Option<SyncStatus>, TrkError> {
+        git_sync_status(&self.trk_dir(), sync)
+    }
+}
@@ -0,0 +1,337 @@
+/* Parsing of the time arguments accepted by begin, end, pause, resume, note and report.
+ *
+ * A time specification is either relative to now or an absolute point in time:
+ *   durations ago:  90m, 1h30, 1h 30m, 2 hours, one week, 3 days ago, 01:30 (HH:MM)
+ *   absolute:       2026-10-01, 2026-10-01T09:00, 2026-10-01 09:00:15
+ *   named days:     now, today 09:00, yesterday 17:00, monday, last monday 08:30
+ * Any of these may be prefixed with "since=" (or "since "), which reads better for reports.
+ * Named days without a clock time refer to the beginning of that day.
+ * Dates and clock times are wall clock times in the timezone of now.
+ */
+
+use chrono::{DateTime, Datelike, Duration, LocalResult, NaiveDate, NaiveDateTime};
+use chrono::{NaiveTime, TimeZone, Weekday};
+use chrono_tz::Tz;
+
+/** Parse a time specification into an absolute unix timestamp in milliseconds.
+ * Times in the future are rejected, as are strings that can't be parsed.
+ */
+pub fn parse_time(spec: &str, now: DateTime<Tz>) -> Result<u64, String> {
+    let normalized = spec.trim().to_lowercase();
+    let normalized = normalized
+        .strip_prefix("since=")
+        .or_else(|| normalized.strip_prefix("since "))
+        .unwrap_or(&normalized)
+        .trim();
+    if normalized.is_empty() {
+        return Err(String::from("empty time specification"));
+    }
+
+    let time = parse_relative(normalized, now)
+        .or_else(|| parse_absolute(normalized, now.timezone()))
+        .or_else(|| parse_named_day(normalized, now))
+        .ok_or_else(|| format!("could not understand '{}'", spec.trim()))??;
+
+    if time > now {
+        return Err(format!(
+            "{} is in the future",
+            time.format("%Y-%m-%d, %H:%M")
+        ));
+    }
+    u64::try_from(time.timestamp_millis()).map_err(|_| String::from("time is before 1970"))
+}
+
+/** Like parse_time, but a bare clock time (HH:MM) is the time on the day of `day`,
+ * or on the day after if that would be before `day`. For times within a past session.
+ */
+pub fn parse_time_on(spec: &str, day: DateTime<Tz>, now: DateTime<Tz>) -> Result<u64, String> {
+    let Some(time) = parse_clock_time(spec.trim()) else {
+        return parse_time(spec, now);
+    };
+    let tz = day.timezone();
+    let mut time = local(day.date_naive().and_time(time), tz)?;
+    if time < day {
+        time = local(
+            day.date_naive().succ_opt().unwrap().and_time(time.time()),
+            tz,
+        )?;
+    }
+    if time > now {
+        return Err(format!(
+            "{} is in the future",
+            time.format("%Y-%m-%d, %H:%M")
+        ));
+    }
+    u64::try_from(time.timestamp_millis()).map_err(|_| String::from("time is before 1970"))
+}
+
+/* "now", "HH:MM" and durations, all meaning that long ago */
+fn parse_relative(spec: &str, now: DateTime<Tz>) -> Option<Result<DateTime<Tz>, String>> {
+    if spec == "now" {
+        return Some(Ok(now));
+    }
+    /* None from the parsers is an amount too large for a duration */
+    let duration = parse_hhmm(spec).or_else(|| parse_duration(spec))?;
+    Some(
+        duration
+            .and_then(|duration| now.checked_sub_signed(duration))
+            .ok_or_else(|| format!("'{spec}' is too long ago")),
+    )
+}
+
+/* The original trk format: HH:MM means that many hours and minutes ago */
+fn parse_hhmm(spec: &str) -> Option<Option<Duration>> {
+    let (hours, minutes) = spec.split_once(':')?;
+    let hours = parse_number(hours)?;
+    let minutes = parse_number(minutes)?;
+    Some(
+        hours
+            .checked_mul(60)
+            .and_then(|hours| hours.checked_add(minutes))
+            .and_then(Duration::try_minutes),
+    )
+}
+
+fn parse_number(digits: &str) -> Option<i64> {
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse().ok()
+}
+
+fn number_word(word: &str) -> Option<i64> {
+    let number = match word {
+        "a" | "an" | "one" => 1,
+        "two" => 2,
+        "three" => 3,
+        "four" => 4,
+        "five" => 5,
+        "six" => 6,
+        "seven" => 7,
+        "eight" => 8,
+        "nine" => 9,
+        "ten" => 10,
+        _ => return None,
+    };
+    Some(number)
+}
+
+fn unit_seconds(unit: &str) -> Option<i64> {
+    let seconds = match unit {
+        "s" | "sec" | "secs" | "second" | "seconds" => 1,
+        "m" | "min" | "mins" | "minute" | "minutes" => 60,
+        "h" | "hr" | "hrs" | "hour" | "hours" => 60 * 60,
+        "d" | "day" | "days" => 24 * 60 * 60,
+        "w" | "week" | "weeks" => 7 * 24 * 60 * 60,
+        _ => return None,
+    };
+    Some(seconds)
+}
+
+/* A sequence of amounts with units, like "1h30m", "1h30", "2 hours 5 minutes" or "one week ago" */
+fn parse_duration(spec: &str) -> Option<Option<Duration>> {
+    let spec = spec.strip_suffix("ago").unwrap_or(spec).trim_end();
+
+    /* Split into alternating number and unit tokens */
+    let mut tokens = Vec::<String>::new();
+    for word in spec.split_whitespace() {
+        if let Some(number) = number_word(word) {
+            tokens.push(number.to_string());
+            continue;
+        }
+        let mut chars = word.chars().peekable();
+        while let Some(&c) = chars.peek() {
+            let is_digit = c.is_ascii_digit();
+            let mut token = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_ascii_digit() != is_digit {
+                    break;
+                }
+                token.push(c);
+                chars.next();
+            }
+            tokens.push(token);
+        }
+    }
+
+    let mut seconds = Some(0i64);
+    let mut last_unit = None;
+    let mut tokens = tokens.iter();
+    while let Some(amount) = tokens.next() {
+        let amount = parse_number(amount)?;
+        let unit = match tokens.next() {
+            Some(unit) => unit_seconds(unit)?,
+            /* A trailing bare number counts in the next smaller unit: 1h30 */
+            None => match last_unit? {
+                3600 => 60,
+                60 => 1,
+                _ => return None,
+            },
+        };
+        seconds = seconds
+            .zip(amount.checked_mul(unit))
+            .and_then(|(seconds, amount)| seconds.checked_add(amount));
+        last_unit = Some(unit);
+    }
+    last_unit.map(|_| seconds.and_then(Duration::try_seconds))
+}
+
+fn parse_absolute(spec: &str, tz: Tz) -> Option<Result<DateTime<Tz>, String>> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(spec) {
+        return Some(Ok(time.with_timezone(&tz)));
+    }
+    const FORMATS: [&str; 4] = [
+        "%Y-%m-%dt%H:%M:%S",
+        "%Y-%m-%dt%H:%M",
+        "%Y-%m-%d %H:%M:%S",
+        "%Y-%m-%d %H:%M",
+    ];
+    if let Some(time) = FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(spec, format).ok())
+    {
+        return Some(local(time, tz));
+    }
+    NaiveDate::parse_from_str(spec, "%Y-%m-%d")
+        .ok()
+        .map(|date| local(date.and_time(midnight()), tz))
+}
+
+/* today, yesterday, monday, last monday, each optionally followed by HH:MM */
+fn parse_named_day(spec: &str, now: DateTime<Tz>) -> Option<Result<DateTime<Tz>, String>> {
+    let mut words = spec.split_whitespace().collect::<Vec<&str>>();
+    let time = match words.last().and_then(|word| parse_clock_time(word)) {
+        Some(time) => {
+            words.pop();
+            time
+        }
+        None => midnight(),
+    };
+
+    let today = now.date_naive();
+    let date = match words.as_slice() {
+        ["today"] => today,
+        ["yesterday"] => today.pred_opt()?,
+        [weekday] => previous_weekday(today, weekday.parse::<Weekday>().ok()?, true),
+        ["last", weekday] => previous_weekday(today, weekday.parse::<Weekday>().ok()?, false),
+        _ => return None,
+    };
+    Some(local(date.and_time(time), now.timezone()))
+}
+
+fn midnight() -> NaiveTime {
+    NaiveTime::from_hms_opt(0, 0, 0).unwrap()
+}
+
+fn parse_clock_time(word: &str) -> Option<NaiveTime> {
+    NaiveTime::parse_from_str(word, "%H:%M")
+        .or_else(|_| NaiveTime::parse_from_str(word, "%H:%M:%S"))
+        .ok()
+}
+
+/* The most recent day with the given weekday, today only counts if include_today */
+fn previous_weekday(today: NaiveDate, weekday: Weekday, include_today: bool) -> NaiveDate {
+    let mut days_back =
+        (7 + today.weekday().num_days_from_monday() - weekday.num_days_from_monday()) % 7;
+    if days_back == 0 && !include_today {
+        days_back = 7;
+    }
+    today - Duration::days(i64::from(days_back))
+}
+
+/* Wall clock time in the timezone tz */
+pub fn local(time: NaiveDateTime, tz: Tz) -> Result<DateTime<Tz>, String> {
+    match tz.from_local_datetime(&time) {
+        LocalResult::Single(time) => Ok(time),
+        /* Repeated hour when the clocks go back, take the first one */
+        LocalResult::Ambiguous(earliest, _) => Ok(earliest),
+        LocalResult::None => Err(format!(
+            "{} does not exist in {tz}",
+            time.format("%Y-%m-%d, %H:%M")
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /* A Sunday at noon */
+    fn now() -> DateTime<Tz> {
+        Tz::UTC.with_ymd_and_hms(2026, 10, 18, 12, 0, 0).unwrap()
+    }
+
+    fn at(day: u32, hour: u32, minute: u32) -> u64 {
+        Tz::UTC
+            .with_ymd_and_hms(2026, 10, day, hour, minute, 0)
+            .unwrap()
+            .timestamp_millis() as u64
+    }
+
+    fn ago(minutes: i64) -> u64 {
+        (now() - Duration::minutes(minutes)).timestamp_millis() as u64
+    }
+
+    #[test]
+    fn hhmm_is_how_long_ago() {
+        assert_eq!(parse_time("01:30", now()), Ok(ago(90)));
+        assert_eq!(parse_time("0:05", now()), Ok(ago(5)));
+        assert_eq!(parse_time("now", now()), Ok(ago(0)));
+    }
+
+    #[test]
+    fn durations_with_units() {
+        assert_eq!(parse_time("90m", now()), Ok(ago(90)));
+        assert_eq!(parse_time("1h30", now()), Ok(ago(90)));
+        assert_eq!(parse_time("1h 30m", now()), Ok(ago(90)));
+        assert_eq!(parse_time("2 hours 5 minutes", now()), Ok(ago(125)));
+        assert_eq!(parse_time("one week ago", now()), Ok(ago(7 * 24 * 60)));
+        assert_eq!(parse_time("since=3 days", now()), Ok(ago(3 * 24 * 60)));
+        assert!(parse_time("5 fortnights", now()).is_err());
+        assert!(parse_time("soon", now()).is_err());
+        assert!(parse_time("", now()).is_err());
+    }
+
+    #[test]
+    fn points_in_time() {
+        assert_eq!(parse_time("2026-10-01T09:00", now()), Ok(at(1, 9, 0)));
+        assert_eq!(parse_time("2026-10-01", now()), Ok(at(1, 0, 0)));
+        assert_eq!(parse_time("yesterday 17:00", now()), Ok(at(17, 17, 0)));
+        assert_eq!(parse_time("today", now()), Ok(at(18, 0, 0)));
+        assert_eq!(parse_time("sunday", now()), Ok(at(18, 0, 0)));
+        assert_eq!(parse_time("last sunday", now()), Ok(at(11, 0, 0)));
+        assert_eq!(parse_time("monday 8:30", now()), Ok(at(12, 8, 30)));
+    }
+
+    #[test]
+    fn out_of_range_amounts_are_errors() {
+        for spec in [
+            "999999999999999:00",
+            "1 s 9223372036854775807 s",
+            "9223372036854775807 w",
+            "100000000 weeks",
+        ] {
+            let error = parse_time(spec, now()).unwrap_err();
+            assert!(error.contains("too long ago"), "{spec}: {error}");
+        }
+        assert!(parse_time("99999999999999999999 s", now()).is_err());
+    }
+
+    #[test]
+    fn future_times_are_refused() {
+        for spec in ["2026-10-19", "today 13:00", "2026-10-18T12:01"] {
+            let error = parse_time(spec, now()).unwrap_err();
+            assert!(error.contains("in the future"), "{spec}: {error}");
+        }
+    }
+
+    #[test]
+    fn clock_times_within_a_session() {
+        let day = Tz::UTC.with_ymd_and_hms(2026, 10, 17, 22, 0, 0).unwrap();
+        assert_eq!(parse_time_on("23:00", day, now()), Ok(at(17, 23, 0)));
+        /* Before the start of the session, so on the next day */
+        assert_eq!(parse_time_on("01:00", day, now()), Ok(at(18, 1, 0)));
+        assert_eq!(parse_time_on("90m", day, now()), Ok(ago(90)));
+        assert!(parse_time_on("21:00", day, now()).is_err());
+    }
+}
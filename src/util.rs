@@ -1,32 +1,51 @@
-use chrono::Duration;
-use chrono::{Local, TimeZone};
-use nom::{do_parse, map_res, named, tag};
-use std::time::{SystemTime, UNIX_EPOCH};
+use chrono::TimeZone;
+use chrono_tz::Tz;
 
-/* For running git and html-tidy */
+/* For running html-tidy */
 use std::process::Command;
 
-use std::env;
+use std::path::Path;
 
-/* For from::utf8 */
-use std::str;
-
-pub fn get_seconds() -> u64 {
-    SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_secs()
-}
-
-pub fn ts_to_date(timestamp: u64) -> String {
-    Local
-        .timestamp_opt(timestamp as i64, 0)
+/* Timestamps are milliseconds since the epoch, shown as wall clock time in tz */
+pub fn ts_to_date(timestamp: u64, tz: Tz) -> String {
+    tz.timestamp_millis_opt(timestamp as i64)
         .unwrap()
         .format("%Y-%m-%d, %H:%M")
         .to_string()
 }
 
-pub fn sec_to_hms_string(seconds: u64) -> String {
+/** The timezone of this machine: $TZ if it names a zone, else what the system says.
+ * UTC if neither is known, rather than guessing an offset without DST rules.
+ */
+pub fn system_timezone() -> Tz {
+    std::env::var("TZ")
+        .ok()
+        .and_then(|name| name.trim_start_matches(':').parse().ok())
+        .or_else(|| iana_time_zone::get_timezone().ok()?.parse().ok())
+        .unwrap_or(Tz::UTC)
+}
+
+/* Escape the characters LaTeX treats specially */
+pub fn tex_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str(r"\textbackslash{}"),
+            '&' | '%' | '$' | '#' | '_' | '{' | '}' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '~' => escaped.push_str(r"\textasciitilde{}"),
+            '^' => escaped.push_str(r"\textasciicircum{}"),
+            '\n' => escaped.push_str(r"\newline{}"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+pub fn ms_to_hms_string(milliseconds: u64) -> String {
+    let seconds = milliseconds / 1000;
     let hours = seconds / 3600;
     let minutes = (seconds - hours * 3600) / 60;
     let seconds = seconds - minutes * 60 - hours * 3600;
@@ -43,221 +62,13 @@ pub fn sec_to_hms_string(seconds: u64) -> String {
     }
 }
 
-/* For parsing time in HH:MM format. */
-named!(duration_hhmm(&[u8]) -> Duration,
-    do_parse!(
-        hour: map_res!(map_res!(nom::digit, str::from_utf8), |s: &str| s.parse::<i64>()) >>
-        tag!(":") >>
-        min:  map_res!(map_res!(nom::digit, str::from_utf8), |s: &str| s.parse::<i64>()) >>
-        /*tag!(":") >>
-        sec: map_res!(map_res!(nom::digit, str::from_utf8), |s: &str| s.parse::<i64>()) >>*/
-        (Duration::minutes(hour * 60 + min))
-    )
-);
-
-pub fn parse_hhmm_to_seconds(timestr: &str) -> Option<u64> {
-    match duration_hhmm(timestr.as_bytes()) {
-        Ok((_, out)) => Some(out.num_seconds() as u64),
-        _ => None,
-    }
-}
-
-pub fn set_to_trk_dir() -> bool {
-    let mut path = env::current_dir().unwrap();
-    loop {
-        path.push(".trk");
-        path.pop();
-        if path.exists() {
-            env::set_current_dir(&path).unwrap();
-            return true;
-        } else if !path.pop() {
-            println!("Fatal: not a .trk directory (or subdirectory of one).");
-            return false;
-        }
-    }
-}
-
-pub fn git_init_trk() -> bool {
-    if !set_to_trk_dir() {
-        println!(
-            "Could not initialise trk internal git repo!\
-                 (couldn't find upper level .trk dir)."
-        );
-        return false;
-    }
-
-    let mut path = env::current_dir().unwrap();
-    path.push(".trk");
-    if path.exists() {
-        env::set_current_dir(&path).unwrap();
-    } else {
-        println!("Couldn't access .trk sub directory to initialise trk internal git repo.");
-        return false;
-    }
-    let output = Command::new("git").arg("init").output();
-    if let Err(e) = output {
-        println!("Could not run git init! Error {e}");
-        return false;
-    }
-    let output = Command::new("git")
-        .arg("add")
-        .arg("timesheet.json")
-        .output();
-    if let Err(e) = output {
-        println!("Could not run git init! Error: {e}");
-        return false;
-    }
-
-    /* Reset current_dir to previous value */
-    path.pop();
-    env::set_current_dir(&path).unwrap();
-    true
-}
-
-pub fn git_commit_trk(message: &str) -> bool {
-    if !set_to_trk_dir() {
-        println!(
-            "Could not commit to trk internal git repo!\
-                 (couldn't find upper level .trk dir)."
-        );
-        return false;
-    }
-
-    let mut p = env::current_dir().unwrap();
-    p.push(".trk");
-    if p.exists() {
-        env::set_current_dir(&p).unwrap();
-    } else {
-        println!("Couldn't access .trk sub directory to commit to trk internal git repo.");
-        return false;
-    }
-    let output = Command::new("git")
-        .arg("commit")
-        .arg("timesheet.json")
-        .arg("-m")
-        .arg(message)
-        .output();
-    if let Err(e) = output {
-        println!("Could not run git commit! Error {e}");
-        return false;
-    }
-
-    /* Reset current_dir to previous value */
-    p.pop();
-    env::set_current_dir(&p).unwrap();
-    true
-}
-
-pub fn git_pull() -> bool {
-    if !set_to_trk_dir() {
-        println!(
-            "Could not pull from git repo!\
-                 (couldn't find upper level .trk dir)."
-        );
-        return false;
-    }
-
-    let mut p = env::current_dir().unwrap();
-    p.push(".trk");
-    if p.exists() {
-        env::set_current_dir(&p).unwrap();
-    } else {
-        println!("Couldn't access .trk sub directory to pull from upstream .trk git repo.");
-        return false;
-    }
-    let output = Command::new("git").arg("pull").output();
-    if let Err(e) = output {
-        println!("Could not run git pull! Error {e}");
-        return false;
-    }
-
-    /* Reset current_dir to previous value */
-    p.pop();
-    env::set_current_dir(&p).unwrap();
-    true
-}
-pub fn git_push() -> bool {
-    if !set_to_trk_dir() {
-        println!(
-            "Could not push to git repo!\
-                 (couldn't find upper level .trk dir)."
-        );
-        return false;
-    }
-
-    let mut p = env::current_dir().unwrap();
-    p.push(".trk");
-    if p.exists() {
-        env::set_current_dir(&p).unwrap();
-    } else {
-        println!("Couldn't access .trk sub directory to push to upstream .trk git repo.");
-        return false;
-    }
-    let output = Command::new("git").arg("push").output();
-    if let Err(e) = output {
-        println!("Could not run git push! Error {e}");
-        return false;
-    }
-
-    /* Reset current_dir to previous value */
-    p.pop();
-    env::set_current_dir(&p).unwrap();
-    true
-}
-
-pub fn git_author() -> Option<String> {
-    if let Ok(output) = Command::new("git").arg("config").arg("user.name").output() {
-        if output.status.success() {
-            let output = String::from_utf8_lossy(&output.stdout);
-            /* Remove trailing newline character */
-            let mut output = output.to_string();
-            output
-                .pop()
-                .expect("Empty name in git config? Not even a newline?!?");
-            Some(output)
-        } else {
-            let output = String::from_utf8_lossy(&output.stderr);
-            println!("git config user.name failed. {output}");
-            None
-        }
-    } else {
-        None
-    }
-}
-
-pub fn git_commit_message(hash: &str) -> Option<String> {
-    if let Ok(output) = Command::new("git")
-        .arg("log")
-        .arg("--format=%B")
-        .arg("-n")
-        .arg("1")
-        .arg(hash)
-        .output()
-    {
-        if output.status.success() {
-            let output = String::from_utf8_lossy(&output.stdout);
-            Some(output.to_string())
-        } else {
-            let output = String::from_utf8_lossy(&output.stderr);
-            println!("git log --format=%B -n 1 <hash> failed. {output}");
-            None
-        }
-    } else {
-        None
-    }
-}
-
-pub fn format_file(filename: &str) {
-    if Command::new("tidy")
+/* Indent an html file with html-tidy, if it is installed */
+pub fn format_file(path: &Path) {
+    let _ = Command::new("tidy")
         .arg("--tidy-mark")
         .arg("no")
         .arg("-i")
         .arg("-m")
-        .arg(filename)
-        .output()
-        .is_ok()
-    {
-    } else {
-        println!("tidy-html not found!");
-    }
+        .arg(path)
+        .output();
 }
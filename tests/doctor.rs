@@ -0,0 +1,82 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use trk::doctor::diagnose;
+use trk::{FakeClock, Storage, Timesheet};
+
+const START: u64 = 1_700_000_000_000;
+const MINUTE: u64 = 60_000;
+
+/* A fresh directory for one test, removed when dropped */
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("trk-test-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/* A sheet saved after every step: initialised, begun and ended */
+fn saved_sheet(storage: &Storage) -> Timesheet {
+    let clock = Arc::new(FakeClock::new(START));
+    let mut sheet = Timesheet::with_clock(String::from("tester"), clock.clone());
+    storage.init(&sheet).unwrap();
+    clock.advance(MINUTE);
+    sheet.new_session(None).unwrap();
+    storage.save(&sheet, "begin").unwrap();
+    clock.advance(60 * MINUTE);
+    sheet.end_session(None).unwrap();
+    storage.save(&sheet, "end").unwrap();
+    sheet
+}
+
+#[test]
+fn a_sound_sheet_has_nothing_to_repair() {
+    let dir = TempDir::new("doctor-sound");
+    let storage = Storage::new(&dir.0);
+    saved_sheet(&storage);
+
+    let diagnosis = diagnose(&storage).unwrap();
+    assert!(diagnosis.problems.is_empty());
+    assert!(diagnosis.last_good.is_none());
+    assert_eq!(diagnosis.report(), "No problems found.\n");
+}
+
+#[test]
+fn corrupt_writes_go_back_to_the_last_good_revision() {
+    let dir = TempDir::new("doctor-corrupt");
+    let storage = Storage::new(&dir.0);
+    let sheet = saved_sheet(&storage);
+    let good = storage.resolve("HEAD").unwrap();
+
+    /* A committed session which ends before it starts */
+    let mut broken: serde_json::Value = serde_json::from_str(&sheet.to_json()).unwrap();
+    broken["sessions"][0]["end"] = (START - MINUTE).into();
+    let broken = Timesheet::parse(&broken.to_string()).unwrap();
+    assert!(!broken.check().is_empty());
+    storage.save(&broken, "broken").unwrap();
+
+    let diagnosis = diagnose(&storage).unwrap();
+    assert!(!diagnosis.problems.is_empty());
+    let last_good = diagnosis.last_good.unwrap();
+    assert_eq!(last_good.hash, good);
+    assert_eq!(last_good.subject, "end");
+
+    /* And a file which isn't JSON at all on top of it */
+    fs::write(dir.0.join(".trk/timesheet.json"), "{\"sessions\": [").unwrap();
+    let diagnosis = diagnose(&storage).unwrap();
+    assert_eq!(diagnosis.problems.len(), 1);
+    assert!(diagnosis.report().contains("Last good revision:"));
+    assert_eq!(diagnosis.last_good.unwrap().hash, good);
+    assert!(storage.revision(&good).unwrap().check().is_empty());
+}
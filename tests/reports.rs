@@ -0,0 +1,128 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use trk::{FakeClock, Filter, HasTEX, Storage, Timesheet};
+
+const START: u64 = 1_700_000_000_000;
+const MINUTE: u64 = 60_000;
+
+/* A fresh directory for one test, removed when dropped */
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("trk-test-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/* A finalized session of 2 hours of work with a pause of 30 minutes and a note */
+fn sheet(clock: &Arc<FakeClock>) -> Timesheet {
+    let mut sheet = Timesheet::with_clock(String::from("tester"), clock.clone());
+    clock.advance(MINUTE);
+    sheet.new_session(None).unwrap();
+    clock.advance(30 * MINUTE);
+    sheet
+        .note(None, String::from("<script>escape me</script>"), vec![])
+        .unwrap();
+    clock.advance(30 * MINUTE);
+    sheet.pause(None, Some(String::from("lunch"))).unwrap();
+    clock.advance(30 * MINUTE);
+    sheet.resume(None).unwrap();
+    clock.advance(60 * MINUTE);
+    sheet.end_session(None).unwrap();
+    sheet
+}
+
+#[test]
+fn reports_are_written_next_to_trk() {
+    let dir = TempDir::new("reports");
+    let clock = Arc::new(FakeClock::new(START));
+    let storage = Storage::new(&dir.0);
+    let sheet = sheet(&clock);
+    storage.init(&sheet).unwrap();
+    storage.write_reports(&sheet).unwrap();
+
+    let html = fs::read_to_string(dir.0.join("timesheet.html")).unwrap();
+    assert!(html.contains("tester"));
+    assert!(html.contains("Worked for 2 hours<"));
+    assert!(html.contains("30 minutes"));
+    assert!(html.contains("lunch"));
+    assert!(html.contains("&lt;script&gt;escape me"));
+    assert!(!html.contains("<script>"));
+
+    let html = fs::read_to_string(dir.0.join("session.html")).unwrap();
+    assert!(html.contains("lunch"));
+}
+
+#[test]
+fn saved_sheet_loads_unchanged() {
+    let dir = TempDir::new("load");
+    let clock = Arc::new(FakeClock::new(START));
+    let storage = Storage::new(&dir.0);
+    let sheet = sheet(&clock);
+    storage.init(&sheet).unwrap();
+
+    let loaded = Storage::discover(&dir.0).unwrap().load().unwrap().unwrap();
+    assert_eq!(loaded.to_json(), sheet.to_json());
+    assert_eq!(storage.history().unwrap().len(), 1);
+}
+
+#[test]
+fn running_session_counts_up_to_the_clock() {
+    let dir = TempDir::new("running");
+    let clock = Arc::new(FakeClock::new(START));
+    let storage = Storage::new(&dir.0);
+    let mut sheet = Timesheet::with_clock(String::from("tester"), clock.clone());
+    clock.advance(MINUTE);
+    sheet.new_session(None).unwrap();
+    clock.advance(45 * MINUTE);
+    storage.init(&sheet).unwrap();
+    storage.write_reports(&sheet).unwrap();
+
+    let html = fs::read_to_string(dir.0.join("session.html")).unwrap();
+    assert!(html.contains("Worked for 45 minutes"));
+}
+
+#[test]
+fn tex_report_of_the_selected_sessions() {
+    let clock = Arc::new(FakeClock::new(START));
+    let mut sheet = sheet(&clock);
+    sheet
+        .tag_session(None, Some(String::from("acme")), vec![])
+        .unwrap();
+    clock.advance(MINUTE);
+    sheet.new_session(None).unwrap();
+    sheet
+        .note(None, String::from("other project"), vec![])
+        .unwrap();
+    clock.advance(60 * MINUTE);
+    sheet.end_session(None).unwrap();
+
+    let tex = sheet.to_tex(sheet.now(), sheet.shown_in());
+    assert!(tex.contains("other project"));
+    assert!(tex.contains("Worked for & 3 hours"));
+
+    sheet.select(Filter {
+        project: Some(String::from("acme")),
+        ..Filter::default()
+    });
+    let tex = sheet.to_tex(sheet.now(), sheet.shown_in());
+    assert!(tex.starts_with("\\documentclass"));
+    assert!(tex.contains("lunch"));
+    assert!(!tex.contains("other project"));
+    assert!(tex.contains("Worked for & 2 hours"));
+
+    /* Sessions render on their own as well */
+    let session = &sheet.sessions()[0];
+    assert!(session.to_tex(sheet.now(), None).contains("lunch"));
+}
@@ -0,0 +1,91 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use git2::Repository;
+
+/* A fresh directory for one test, removed when dropped */
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("trk-test-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/* Run trk in dir like a user would and fail the test if it fails */
+fn trk(dir: &Path, args: &[&str]) {
+    let output = Command::new(env!("CARGO_BIN_EXE_trk"))
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "trk {args:?} failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+/* Subject of the newest commit on the remote */
+fn remote_head(remote: &Path) -> String {
+    let repo = Repository::open_bare(remote).unwrap();
+    let head = repo.head().unwrap().peel_to_commit().unwrap();
+    head.summary().unwrap().to_string()
+}
+
+fn sync_mode(machine: &Path) -> Option<String> {
+    let repo = Repository::open(machine.join(".trk")).unwrap();
+    let config = repo
+        .config()
+        .unwrap()
+        .open_level(git2::ConfigLevel::Local)
+        .unwrap();
+    config.get_string("trk.sync.mode").ok()
+}
+
+#[test]
+fn sync_mode_stays_on_its_machine() {
+    let dir = TempDir::new("sync");
+    let remote = dir.0.join("remote.git");
+    let laptop = dir.0.join("laptop");
+    let desktop = dir.0.join("desktop");
+    Repository::init_bare(&remote).unwrap();
+    fs::create_dir_all(&laptop).unwrap();
+    trk(&laptop, &["init", "tester"]);
+    Repository::open(laptop.join(".trk"))
+        .unwrap()
+        .remote("origin", remote.to_str().unwrap())
+        .unwrap();
+    trk(&laptop, &["sync"]);
+    Repository::clone(remote.to_str().unwrap(), desktop.join(".trk")).unwrap();
+
+    /* Turning sync off on the laptop doesn't turn it off on the desktop */
+    trk(&laptop, &["set-sync", "off"]);
+    trk(&laptop, &["begin"]);
+    assert_eq!(remote_head(&remote), "initialise trk");
+    trk(&laptop, &["sync"]);
+    assert_eq!(remote_head(&remote), "begin new session");
+    trk(&desktop, &["end"]);
+    assert_eq!(remote_head(&remote), "end session");
+    assert_eq!(sync_mode(&laptop).as_deref(), Some("off"));
+    assert_eq!(sync_mode(&desktop), None);
+
+    /* A change of mode applies to the command right after it */
+    trk(&desktop, &["begin"]);
+    trk(&laptop, &["set-sync", "pull-only"]);
+    trk(&laptop, &["note", "from the laptop"]);
+    assert_eq!(remote_head(&remote), "begin new session");
+    trk(&laptop, &["set-sync", "on-command"]);
+    trk(&laptop, &["end"]);
+    assert_eq!(remote_head(&remote), "end session");
+}